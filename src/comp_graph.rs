@@ -1,11 +1,17 @@
+use crate::ops::{BuiltinOp, Scalar};
+use crate::pretty::{self, Expression, Term};
+use crate::stats::{self, CacheStats};
+use crate::trace::{self, TraceEventKind};
 use std::borrow::Cow;
 use std::cell::{Cell, RefCell};
+use std::fmt::{Display, Formatter};
 use std::marker::PhantomData;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 
 pub struct OperationNodeInner<T, Op: Operation + ?Sized> {
     cache: Cell<Option<T>>,
     dependents: RefCell<Vec<Rc<dyn Cached>>>,
+    stats: Cell<CacheStats>,
     operation: Op,
 }
 
@@ -23,13 +29,63 @@ impl<Op: Operation + ?Sized> OperationNode<Op> {
     /// Computes and returns result of computational graph with root at this node.
     pub fn compute(&self) -> Op::Output {
         let cache = self.cache.clone().into_inner();
+        if cache.is_some() {
+            self.observe(TraceEventKind::CacheHit);
+        }
 
         cache.unwrap_or_else(|| {
             let new = self.operation.execute();
             self.cache.set(Some(new));
+            self.observe(TraceEventKind::NodeComputed);
             new
         })
     }
+
+    /// Name of the operation of this node if it has one
+    pub fn name(&self) -> Option<&str> {
+        self.operation.name()
+    }
+
+    /// Cache counters of this node
+    pub fn cache_stats(&self) -> CacheStats {
+        self.stats.get()
+    }
+
+    pub fn reset_cache_stats(&self) {
+        self.stats.set(CacheStats::default());
+    }
+
+    fn observe(&self, kind: TraceEventKind) {
+        // reading or replacing a value given from outside is not caching
+        if !self.operation.is_source() {
+            stats::record(&self.stats, kind);
+        }
+        trace::emit(kind, trace::address(self), self.name());
+    }
+}
+
+/// Prints the node as an infix expression, see [`pretty`](crate::pretty)
+impl<Op: Operation + ?Sized> Display for OperationNode<Op> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        pretty::write(f, &NodeExpr(self))
+    }
+}
+
+/// [`Expression`] of a node, which can't be one itself when its operation is unsized
+pub(crate) struct NodeExpr<'a, Op: ?Sized + Operation>(pub(crate) &'a OperationNode<Op>);
+
+impl<'a, Op: ?Sized + Operation> Expression<'a> for NodeExpr<'a, Op> {
+    fn id(&self) -> usize {
+        trace::address(self.0)
+    }
+
+    fn term(&self) -> Term<'_> {
+        self.0.operation.term()
+    }
+
+    fn for_each_input(&self, f: &mut dyn FnMut(Box<dyn Expression<'a> + 'a>)) {
+        self.0.operation.for_each_input(f)
+    }
 }
 impl<Op: Operation> OperationNode<Op> {
     /// Creates new node with `operation`.
@@ -37,6 +93,7 @@ impl<Op: Operation> OperationNode<Op> {
         let out = Rc::new(OperationNode {
             cache: Cell::new(None),
             dependents: RefCell::new(vec![]),
+            stats: Default::default(),
             operation,
         });
         let as_dep = out.clone() as Rc<dyn Cached>;
@@ -55,6 +112,7 @@ impl<T: Copy + 'static> InputNode<T> {
             operation: InputOp(name.into(), PhantomData),
             cache: Cell::new(None),
             dependents: Default::default(),
+            stats: Default::default(),
         })
     }
 
@@ -62,6 +120,44 @@ impl<T: Copy + 'static> InputNode<T> {
     pub fn set(&self, data: T) {
         self.invalidate_cache();
         self.cache.set(Some(data));
+        self.observe(TraceEventKind::InputSet);
+    }
+}
+
+/// Constant node of computational graph
+pub type ConstantNode<T> = OperationNode<ConstantOp<T>>;
+
+impl<T: Copy + Display + 'static> ConstantNode<T> {
+    /// Creates node with fixed `value`, which is not an input but can still be changed with [`set`](Self::set)
+    pub fn new_constant(value: T) -> Rc<Self> {
+        OperationNode::new(ConstantOp {
+            name: None,
+            value: Cell::new(value),
+            format: |value| value.to_string(),
+        })
+    }
+
+    /// Same as [`new_constant`](Self::new_constant) but named,
+    /// e.g. for coefficients of a model that are tuned rather than fed
+    pub fn new_parameter(name: impl Into<Cow<'static, str>>, value: T) -> Rc<Self> {
+        OperationNode::new(ConstantOp {
+            name: Some(name.into()),
+            value: Cell::new(value),
+            format: |value| value.to_string(),
+        })
+    }
+}
+
+impl<T: Copy + 'static> ConstantNode<T> {
+    /// Changes value of this constant, invalidating its dependents
+    pub fn set(&self, value: T) {
+        self.operation.value.set(value);
+        self.invalidate_cache();
+        self.observe(TraceEventKind::InputSet);
+    }
+
+    pub fn value(&self) -> T {
+        self.operation.value.get()
     }
 }
 
@@ -82,6 +178,41 @@ pub fn new_binary<Prev1: ?Sized + Operation, Prev2: ?Sized + Operation, Out: Cop
     OperationNode::new(((arg1, arg2), move |(x1, x2)| f(x1, x2)))
 }
 
+/// Creates node computing `if_true` or `if_false` depending on `condition`.
+///
+/// Only the chosen branch is computed and the node depends only on it,
+/// so changes of the other branch don't invalidate it.
+pub fn new_select<C, A, B>(
+    condition: Rc<OperationNode<C>>,
+    if_true: Rc<OperationNode<A>>,
+    if_false: Rc<OperationNode<B>>,
+) -> Rc<OperationNode<SelectOp<C, A, B>>>
+where
+    C: ?Sized + Operation<Output = bool>,
+    A: ?Sized + Operation,
+    B: ?Sized + Operation<Output = A::Output>,
+{
+    OperationNode::new(SelectOp {
+        condition,
+        is_true: |condition| condition,
+        if_true,
+        if_false,
+        this: RefCell::new(None),
+    })
+}
+
+/// Creates node taking output `I` of a node with multiple outputs returned as a tuple.
+///
+/// Dependents of the port are invalidated together with the node, but computing
+/// several ports runs the operation of the node only once.
+pub fn new_port<const I: usize, Op>(node: Rc<OperationNode<Op>>) -> Rc<OperationNode<PortOp<Op, I>>>
+where
+    Op: ?Sized + Operation,
+    Op::Output: Port<I>,
+{
+    OperationNode::new(PortOp(node))
+}
+
 /// Trait for operations to be supported by computational graph
 ///
 /// Implement it if you want
@@ -91,6 +222,25 @@ pub trait Operation: 'static {
 
     /// Adds a dependent node to all our dependencies
     fn notify_deps(&self, current: Rc<dyn Cached>);
+
+    /// Name of the operation for printing, if it is known
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    /// How the operation is printed by [`pretty`](crate::pretty)
+    fn term(&self) -> Term<'_> {
+        Term::Call(self.name().unwrap_or("custom").into())
+    }
+
+    /// Calls `f` for every input of the operation, for printing
+    fn for_each_input<'a>(&'a self, _f: &mut dyn FnMut(Box<dyn Expression<'a> + 'a>)) {}
+
+    /// Whether the node holds a value given from outside, like inputs and constants,
+    /// so it keeps no cache counters
+    fn is_source(&self) -> bool {
+        false
+    }
 }
 
 /// Noop operation to indicate input node
@@ -104,6 +254,51 @@ impl<T: Copy + 'static> Operation for InputOp<T> {
     }
 
     fn notify_deps(&self, _current: Rc<dyn Cached>) {}
+
+    fn name(&self) -> Option<&str> {
+        Some(&self.0)
+    }
+
+    fn term(&self) -> Term<'_> {
+        Term::Atom(self.0.as_ref().into())
+    }
+
+    fn is_source(&self) -> bool {
+        true
+    }
+}
+
+/// Operation of nodes created by [`ConstantNode::new_constant`] and [`ConstantNode::new_parameter`]
+pub struct ConstantOp<T> {
+    name: Option<Cow<'static, str>>,
+    value: Cell<T>,
+    // captured by the constructors, so printing doesn't require `T: Display`
+    format: fn(T) -> String,
+}
+
+impl<T: Copy + 'static> Operation for ConstantOp<T> {
+    type Output = T;
+
+    fn execute(&self) -> Self::Output {
+        self.value.get()
+    }
+
+    fn notify_deps(&self, _current: Rc<dyn Cached>) {}
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn term(&self) -> Term<'_> {
+        match &self.name {
+            Some(name) => Term::Atom(name.as_ref().into()),
+            None => Term::Atom((self.format)(self.value.get()).into()),
+        }
+    }
+
+    fn is_source(&self) -> bool {
+        true
+    }
 }
 
 impl<T: Copy + 'static, F, O: Copy> Operation
@@ -122,7 +317,140 @@ where
             x.dependents.borrow_mut().push(current.clone());
         }
     }
+
+    fn for_each_input<'a>(&'a self, f: &mut dyn FnMut(Box<dyn Expression<'a> + 'a>)) {
+        for x in &self.0 {
+            f(Box::new(NodeExpr(&**x)));
+        }
+    }
+}
+/// Operation of nodes created by [`new_select`] and [`builtins::select`]
+pub struct SelectOp<C: ?Sized + Operation, A: ?Sized + Operation, B: ?Sized + Operation> {
+    condition: Rc<OperationNode<C>>,
+    is_true: fn(C::Output) -> bool,
+    if_true: Rc<OperationNode<A>>,
+    if_false: Rc<OperationNode<B>>,
+    // node of this operation, weak so it doesn't own itself
+    this: RefCell<Option<Weak<dyn Cached>>>,
 }
+
+impl<C, A, B> Operation for SelectOp<C, A, B>
+where
+    C: ?Sized + Operation,
+    A: ?Sized + Operation,
+    B: ?Sized + Operation<Output = A::Output>,
+{
+    type Output = A::Output;
+
+    fn execute(&self) -> Self::Output {
+        let condition = (self.is_true)(self.condition.compute());
+        let is_condition = |ptr: *const ()| ptr == Rc::as_ptr(&self.condition).cast();
+        let (value, chosen, other, other_is_condition) = if condition {
+            let value = self.if_true.compute();
+            let other_is_condition = is_condition(Rc::as_ptr(&self.if_false).cast());
+            (
+                value,
+                &self.if_true.dependents,
+                &self.if_false.dependents,
+                other_is_condition,
+            )
+        } else {
+            let value = self.if_false.compute();
+            let other_is_condition = is_condition(Rc::as_ptr(&self.if_true).cast());
+            (
+                value,
+                &self.if_false.dependents,
+                &self.if_true.dependents,
+                other_is_condition,
+            )
+        };
+
+        if let Some(this) = self.this.borrow().as_ref().and_then(Weak::upgrade) {
+            let is_this = |x: &Rc<dyn Cached>| std::ptr::addr_eq(Rc::as_ptr(x), Rc::as_ptr(&this));
+            // the condition is always a dependency, also when it is the other branch
+            if !other_is_condition {
+                other.borrow_mut().retain(|x| !is_this(x));
+            }
+            let mut chosen = chosen.borrow_mut();
+            if !chosen.iter().any(is_this) {
+                chosen.push(this.clone());
+            }
+        }
+        value
+    }
+
+    fn notify_deps(&self, current: Rc<dyn Cached>) {
+        *self.this.borrow_mut() = Some(Rc::downgrade(&current));
+        self.condition.dependents.borrow_mut().push(current);
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some("select")
+    }
+
+    fn term(&self) -> Term<'_> {
+        Term::Builtin(BuiltinOp::Select)
+    }
+
+    fn for_each_input<'a>(&'a self, f: &mut dyn FnMut(Box<dyn Expression<'a> + 'a>)) {
+        f(Box::new(NodeExpr(&*self.condition)));
+        f(Box::new(NodeExpr(&*self.if_true)));
+        f(Box::new(NodeExpr(&*self.if_false)));
+    }
+}
+
+/// Output `I` of a value produced by a node with multiple outputs
+pub trait Port<const I: usize>: Copy {
+    type Item: Copy;
+    fn get(self) -> Self::Item;
+}
+
+/// Implements [`Port`] for every element of a tuple
+macro_rules! impl_ports {
+    ($all:tt $($id:tt $item:ident)+) => {
+        $( impl_port!{ $all $id $item } )+
+    };
+}
+
+macro_rules! impl_port {
+    ([$($all:ident)+] $id:tt $item:ident) => {
+        impl<$($all: Copy),+> Port<$id> for ($($all,)+) {
+            type Item = $item;
+
+            fn get(self) -> Self::Item {
+                self.$id
+            }
+        }
+    };
+}
+
+impl_ports!([A0 A1] 0 A0 1 A1);
+impl_ports!([A0 A1 A2] 0 A0 1 A1 2 A2);
+impl_ports!([A0 A1 A2 A3] 0 A0 1 A1 2 A2 3 A3);
+
+/// Operation of nodes created by [`new_port`]
+pub struct PortOp<Op: ?Sized + Operation, const I: usize>(Rc<OperationNode<Op>>);
+
+impl<Op, const I: usize> Operation for PortOp<Op, I>
+where
+    Op: ?Sized + Operation,
+    Op::Output: Port<I>,
+{
+    type Output = <Op::Output as Port<I>>::Item;
+
+    fn execute(&self) -> Self::Output {
+        self.0.compute().get()
+    }
+
+    fn notify_deps(&self, current: Rc<dyn Cached>) {
+        self.0.dependents.borrow_mut().push(current);
+    }
+
+    fn for_each_input<'a>(&'a self, f: &mut dyn FnMut(Box<dyn Expression<'a> + 'a>)) {
+        f(Box::new(NodeExpr(&*self.0)));
+    }
+}
+
 /// Implements [`Operation`] for multiple statically known inputs
 macro_rules! impl_tuples {
     ($token:ident $id:tt $($tail:tt)*) => {
@@ -150,10 +478,24 @@ macro_rules! impl_tuple {
                 )+
 
             }
+
+            fn for_each_input<'a>(&'a self, f: &mut dyn FnMut(Box<dyn Expression<'a> + 'a>)) {
+                reverse_inputs!(self f [$($ids)+]);
+            }
         }
     };
 }
 
+/// Calls `f` for the inputs in the original order, as the macros list them reversed
+macro_rules! reverse_inputs {
+    ($self:ident $f:ident [] $($reversed:tt)*) => {
+        $( $f(Box::new(NodeExpr(&*$self.0.$reversed))); )*
+    };
+    ($self:ident $f:ident [$first:tt $($rest:tt)*] $($reversed:tt)*) => {
+        reverse_inputs!($self $f [$($rest)*] $first $($reversed)*)
+    };
+}
+
 macro_rules! reverse {
     ($self:ident [] $($reversed:tt)*) => {
         ( $($self.0.$reversed.compute(),)*)
@@ -163,7 +505,131 @@ macro_rules! reverse {
     };
 }
 
-impl_tuples!(D 3 C 2 B 1 A 0);
+impl_tuples!(A11 11 A10 10 A9 9 A8 8 A7 7 A6 6 A5 5 A4 4 A3 3 A2 2 A1 1 A0 0);
+
+impl<Op: ?Sized + Operation, F, O: Copy, const N: usize> Operation
+    for ([Rc<OperationNode<Op>>; N], F)
+where
+    F: 'static + Fn([Op::Output; N]) -> O,
+{
+    type Output = O;
+
+    fn execute(&self) -> Self::Output {
+        self.1(self.0.each_ref().map(|x| x.compute()))
+    }
+
+    fn notify_deps(&self, current: Rc<dyn Cached>) {
+        for x in &self.0 {
+            x.dependents.borrow_mut().push(current.clone());
+        }
+    }
+
+    fn for_each_input<'a>(&'a self, f: &mut dyn FnMut(Box<dyn Expression<'a> + 'a>)) {
+        for x in &self.0 {
+            f(Box::new(NodeExpr(&**x)));
+        }
+    }
+}
+
+/// Implements [`Operation`] for [`BuiltinOp`] applied to statically known inputs of the same type
+macro_rules! impl_builtin_tuple {
+    ($($generics:ident $ids:tt)+) => {
+        impl<T: Scalar, $($generics : ?Sized + Operation<Output = T>),+> Operation for ( ($(Rc<OperationNode<$generics>>,)+) , BuiltinOp)
+        {
+            type Output = T;
+
+            fn execute(&self) -> Self::Output {
+                // computes all inputs, also both branches of a select,
+                // which is lazy only when created by `builtins::select`
+                self.1.apply(&[$(self.0.$ids.compute()),+])
+            }
+
+            fn notify_deps(&self, current: Rc<dyn Cached>) {
+                $(
+                    self.0.$ids.dependents.borrow_mut().push(current.clone());
+                )+
+            }
+
+            fn name(&self) -> Option<&str> {
+                Some(self.1.name())
+            }
+
+            fn term(&self) -> Term<'_> {
+                Term::Builtin(self.1)
+            }
+
+            fn for_each_input<'a>(&'a self, f: &mut dyn FnMut(Box<dyn Expression<'a> + 'a>)) {
+                $( f(Box::new(NodeExpr(&*self.0.$ids))); )+
+            }
+        }
+    };
+}
+
+impl_builtin_tuple!(A 0);
+impl_builtin_tuple!(A 0 B 1);
+impl_builtin_tuple!(A 0 B 1 C 2);
+
+/// Helpers creating nodes for each [`BuiltinOp`]
+pub mod builtins {
+    use super::*;
+
+    macro_rules! builtin_fns {
+        ($($name:ident $op:ident ($($args:ident $generics:ident),+);)+) => {$(
+            #[doc = concat!("Creates new node applying [`BuiltinOp::", stringify!($op), "`]")]
+            pub fn $name<T: Scalar, $($generics: ?Sized + Operation<Output = T>),+>(
+                $($args: Rc<OperationNode<$generics>>),+
+            ) -> Rc<OperationNode<(($(Rc<OperationNode<$generics>>,)+), BuiltinOp)>> {
+                OperationNode::new((($($args,)+), BuiltinOp::$op))
+            }
+        )+};
+    }
+
+    builtin_fns! {
+        add Add(x A, y B);
+        sub Sub(x A, y B);
+        mul Mul(x A, y B);
+        div Div(x A, y B);
+        neg Neg(x A);
+        sin Sin(x A);
+        cos Cos(x A);
+        tan Tan(x A);
+        exp Exp(x A);
+        ln Ln(x A);
+        sqrt Sqrt(x A);
+        pow Pow(x A, y B);
+        min Min(x A, y B);
+        max Max(x A, y B);
+        abs Abs(x A);
+        clamp Clamp(x A, low B, high C);
+        lt Lt(x A, y B);
+        le Le(x A, y B);
+        gt Gt(x A, y B);
+        ge Ge(x A, y B);
+        eq Eq(x A, y B);
+        ne Ne(x A, y B);
+    }
+
+    /// Creates new node applying [`BuiltinOp::Select`], like [`new_select`]
+    /// it computes only the chosen branch and depends only on it
+    pub fn select<T: Scalar, A, B, C>(
+        condition: Rc<OperationNode<A>>,
+        if_true: Rc<OperationNode<B>>,
+        if_false: Rc<OperationNode<C>>,
+    ) -> Rc<OperationNode<SelectOp<A, B, C>>>
+    where
+        A: ?Sized + Operation<Output = T>,
+        B: ?Sized + Operation<Output = T>,
+        C: ?Sized + Operation<Output = T>,
+    {
+        OperationNode::new(SelectOp {
+            condition,
+            is_true: |condition| condition != T::ZERO,
+            if_true,
+            if_false,
+            this: RefCell::new(None),
+        })
+    }
+}
 
 pub trait Cached {
     fn invalidate_cache(&self);
@@ -171,13 +637,16 @@ pub trait Cached {
 
 impl<Op: Operation> Cached for OperationNode<Op> {
     fn invalidate_cache(&self) {
-        self.cache.set(None);
+        if self.cache.take().is_some() {
+            self.observe(TraceEventKind::CacheInvalidated);
+        }
         for x in self.dependents.borrow().iter() {
             x.invalidate_cache();
         }
     }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
     use std::ops::Add;
@@ -259,4 +728,186 @@ mod tests {
         let result = new_unary(result, |x| x + 2);
         assert_eq!(result.compute(), 12);
     }
+
+    #[test]
+    fn test_wide() {
+        let inputs: [_; 12] = std::array::from_fn(|x| {
+            let input = InputNode::new_input(x.to_string());
+            input.set(x as i32);
+            input
+        });
+        let [x0, x1, x2, x3, x4, x5, x6, x7, x8, x9, x10, x11] = inputs.clone();
+
+        let tuple = OperationNode::new((
+            (x0, x1, x2, x3, x4, x5, x6, x7, x8, x9, x10, x11),
+            |x: (i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32, i32)| x.0 + x.11,
+        ));
+        let array = OperationNode::new((inputs.clone(), |x: [i32; 12]| x.iter().sum::<i32>()));
+        assert_eq!(tuple.compute(), 11);
+        assert_eq!(array.compute(), 66);
+
+        inputs[11].set(0);
+        assert_eq!(tuple.compute(), 0);
+        assert_eq!(array.compute(), 55);
+    }
+
+    #[test]
+    fn test_select() {
+        let x = InputNode::new_input("x");
+        let y = InputNode::new_input("y");
+        x.set(1.0f32);
+        y.set(2.0f32);
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let expensive = new_unary(y.clone(), move |y: f32| {
+            counter.set(counter.get() + 1);
+            y * 100.0
+        });
+        let condition = new_unary(x.clone(), |x: f32| x > 0.0);
+        let result = new_select(condition, x.clone(), expensive.clone());
+
+        assert_eq!(result.compute(), 1.0);
+        assert_eq!(calls.get(), 0);
+        // the other branch is not a dependency
+        y.set(3.0);
+        assert_eq!(result.cache.get(), Some(1.0));
+
+        x.set(-1.0);
+        assert_eq!(result.compute(), 300.0);
+        assert_eq!(calls.get(), 1);
+        y.set(4.0);
+        assert_eq!(result.cache.get(), None);
+        assert_eq!(result.compute(), 400.0);
+        // switching back stops tracking the expensive branch
+        x.set(2.0);
+        assert_eq!(result.compute(), 2.0);
+        y.set(5.0);
+        assert_eq!(result.cache.get(), Some(2.0));
+        assert_eq!(calls.get(), 2);
+
+        let condition = builtins::gt(x.clone(), builtins::sub(x.clone(), x.clone()));
+        let builtin = builtins::select(condition, expensive.clone(), x.clone());
+        assert_eq!(builtin.compute(), 500.0);
+        assert_eq!(calls.get(), 3);
+        x.set(-1.0);
+        assert_eq!(builtin.compute(), -1.0);
+        assert_eq!(calls.get(), 3);
+        y.set(6.0);
+        assert_eq!(builtin.cache.get(), Some(-1.0));
+        assert_eq!(builtin.compute(), -1.0);
+        assert_eq!(calls.get(), 3);
+        assert_eq!(builtin.to_string(), "select(x > x - x, custom(y), x)");
+    }
+
+    #[test]
+    fn test_select_condition_branch() {
+        let c = InputNode::new_input("c");
+        let x = InputNode::new_input("x");
+        c.set(0.0f32);
+        x.set(2.0);
+        let result = builtins::select(c.clone(), c.clone(), x.clone());
+        assert_eq!(result.compute(), 2.0);
+        // the untaken branch is the condition, which is still a dependency
+        c.set(3.0);
+        assert_eq!(result.cache.get(), None);
+        assert_eq!(result.compute(), 3.0);
+        x.set(4.0);
+        assert_eq!(result.cache.get(), Some(3.0));
+    }
+
+    #[test]
+    fn test_ports() {
+        let x = InputNode::new_input("x");
+        let y = InputNode::new_input("y");
+        x.set(1.0f32);
+        y.set(2.0f32);
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let sin_cos = new_unary(x.clone(), move |x: f32| {
+            counter.set(counter.get() + 1);
+            x.sin_cos()
+        });
+        let sin = new_port::<0, _>(sin_cos.clone());
+        let cos = new_port::<1, _>(sin_cos.clone());
+        let result = add(
+            sin.clone(),
+            new_binary(cos.clone(), y.clone(), |c, y| c * y),
+        );
+
+        assert_eq!(result.compute(), 1f32.sin() + 1f32.cos() * 2.0);
+        assert_eq!(calls.get(), 1);
+        y.set(3.0);
+        assert_eq!(sin.cache.get(), Some(1f32.sin()));
+        assert_eq!(result.compute(), 1f32.sin() + 1f32.cos() * 3.0);
+        assert_eq!(calls.get(), 1);
+
+        x.set(2.0);
+        assert_eq!(sin.cache.get(), None);
+        assert_eq!(cos.compute(), 2f32.cos());
+        assert_eq!(sin.compute(), 2f32.sin());
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_constants() {
+        let x = InputNode::new_input("x");
+        let exponent = ConstantNode::new_constant(2.0f32);
+        let offset = ConstantNode::new_parameter("offset", 1.0);
+        let pow = builtins::pow(x.clone(), exponent.clone());
+        let result = builtins::add(pow.clone(), offset.clone());
+        x.set(3.0);
+        assert_eq!(result.compute(), 10.0);
+        assert_eq!(offset.name(), Some("offset"));
+        assert_eq!(exponent.name(), None);
+
+        offset.set(-1.0);
+        assert_eq!(pow.cache.get(), Some(9.0));
+        assert_eq!(result.compute(), 8.0);
+        exponent.set(3.0);
+        assert_eq!(exponent.value(), 3.0);
+        assert_eq!(result.compute(), 26.0);
+        // printed with the current value
+        assert_eq!(result.to_string(), "x^3 + offset");
+    }
+
+    #[test]
+    fn test_builtins() {
+        let x1 = InputNode::new_input("x1");
+        let x2 = InputNode::new_input("x2");
+        x1.set(2.0f32);
+        x2.set(3.0f32);
+
+        let result = builtins::mul(x1.clone(), builtins::neg(x2.clone()));
+        assert_eq!(result.name(), Some("mul"));
+        assert_eq!(x1.name(), Some("x1"));
+        assert_eq!(result.compute(), -6.0);
+        x2.set(1.0);
+        assert_eq!(result.compute(), -2.0);
+    }
+
+    #[test]
+    fn test_display() {
+        use builtins::{add, mul, pow, sin};
+
+        let x1 = InputNode::new_input("x1");
+        let x2 = InputNode::new_input("x2");
+        let x3 = InputNode::new_input("x3");
+        let graph = add(
+            x1.clone(),
+            mul(
+                x2.clone(),
+                sin(add(x2.clone(), pow(x3, ConstantNode::new_constant(3f32)))),
+            ),
+        );
+        assert_eq!(graph.to_string(), "x1 + x2 * sin(x2 + x3^3)");
+
+        let shared = add(x1.clone(), x2);
+        let custom = new_binary(shared.clone(), shared, |x, y| x * y);
+        let dynamic: OperationNodeDyn<f32> = custom;
+        let result = OperationNode::new((vec![dynamic, x1], |args: Vec<f32>| args[0] - args[1]));
+        assert_eq!(
+            result.to_string(),
+            "let t1 = x1 + x2;\ncustom(custom(t1, t1), x1)"
+        );
+    }
 }
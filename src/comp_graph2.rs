@@ -1,12 +1,28 @@
+//! Zero-allocation version of the graph with static dispatch.
+//!
+//! Dependents are registered once by [`Compute::create_reverse_deps`] and never change,
+//! so a select computes only its chosen branch but unlike the other versions
+//! still depends on both, and changes of the other branch invalidate it and its dependents.
+
+use crate::ops::{BuiltinOp, Scalar};
+use crate::stats::{self, CacheStats};
+use crate::trace::{self, TraceEventKind};
 use std::borrow::Cow;
 use std::cell::{Cell, RefCell};
 
+mod dsl;
+
+pub use dsl::{ComputeExt, Literal};
+
 pub struct OperationNode<'a, T, Args, Op> {
     cache: Cell<Option<T>>,
     // can even use qcell::TCell to completely remove runtime cost of interior mutability
     // if performance is critical
     // but it felt too much for a test task
     dependents: RefCell<Vec<&'a dyn Cached>>,
+    // whether this node was already registered as dependent of its args
+    wired: Cell<bool>,
+    stats: Cell<CacheStats>,
     args: Args,
     operation: Op,
 }
@@ -14,13 +30,42 @@ pub struct OperationNode<'a, T, Args, Op> {
 impl<'a, T, Args, Op> OperationNode<'a, T, Args, Op> {
     /// Creates new node with `operation`.
     pub fn new(args: Args, operation: Op) -> Self {
-        let out = OperationNode {
+        OperationNode {
             cache: Cell::new(None),
             dependents: RefCell::new(vec![]),
+            wired: Cell::new(false),
+            stats: Default::default(),
             args,
             operation,
-        };
-        out
+        }
+    }
+
+    /// Cache counters of this node
+    pub fn cache_stats(&self) -> CacheStats {
+        self.stats.get()
+    }
+
+    pub fn reset_cache_stats(&self) {
+        self.stats.set(CacheStats::default());
+    }
+
+    fn observe(&self, kind: TraceEventKind, name: Option<&str>) {
+        stats::record(&self.stats, kind);
+        trace::emit(kind, trace::address(self), name);
+    }
+
+    /// Same as [`observe`](Self::observe) for inputs and constants,
+    /// which keep no cache counters as their values are given from outside
+    fn observe_source(&self, kind: TraceEventKind, name: Option<&str>) {
+        trace::emit(kind, trace::address(self), name);
+    }
+
+    /// Registers `dependent` unless it is already registered
+    fn add_dependent(&self, dependent: &'a dyn Cached) {
+        let mut dependents = self.dependents.borrow_mut();
+        if !dependents.iter().any(|x| std::ptr::addr_eq(*x, dependent)) {
+            dependents.push(dependent);
+        }
     }
 
     fn cached(&self) -> Option<T>
@@ -41,63 +86,225 @@ impl<T: Copy + 'static> InputNode<'_, T> {
             operation: InputOp(name.into()),
             cache: Cell::new(None),
             dependents: Default::default(),
+            wired: Cell::new(true),
+            stats: Default::default(),
             args: (),
         }
     }
 
     /// Set new value for this input
     pub fn set(&self, data: T) {
-        self.invalidate_cache();
+        self.invalidate(false);
         self.cache.set(Some(data));
+        self.observe(TraceEventKind::InputSet, self.name());
+    }
+}
+
+/// Constant node of computational graph
+pub type ConstantNode<'a, T> = OperationNode<'a, T, (), ConstantOp<T>>;
+
+impl<T: Copy + 'static> ConstantNode<'_, T> {
+    /// Creates node with fixed `value`, which is not an input but can still be changed with [`set`](Self::set).
+    ///
+    /// Unlike [`Literal`] it is a node, so it can be shared and updated.
+    pub fn new_constant(value: T) -> Self {
+        Self::with_name(None, value)
+    }
+
+    /// Same as [`new_constant`](Self::new_constant) but named,
+    /// e.g. for coefficients of a model that are tuned rather than fed
+    pub fn new_parameter(name: impl Into<Cow<'static, str>>, value: T) -> Self {
+        Self::with_name(Some(name.into()), value)
+    }
+
+    fn with_name(name: Option<Cow<'static, str>>, value: T) -> Self {
+        Self {
+            operation: ConstantOp {
+                name,
+                value: Cell::new(value),
+            },
+            cache: Cell::new(None),
+            dependents: Default::default(),
+            wired: Cell::new(true),
+            stats: Default::default(),
+            args: (),
+        }
     }
+
+    /// Changes value of this constant, invalidating its dependents
+    pub fn set(&self, value: T) {
+        self.operation.value.set(value);
+        self.invalidate_cache();
+        self.observe(TraceEventKind::InputSet, self.name());
+    }
+}
+
+/// Helper for easier creating of new node for unary operation
+///
+/// Functions returning created nodes should use function pointers instead of closures,
+/// as types of nodes with `impl Fn` are not accepted by the drop checker
+/// when the node is borrowed by its dependents.
+pub fn new_unary<'a, X: Compute<'a>, Out: 'static + Copy, F: Fn(X::Output) -> Out>(
+    arg: X,
+    f: F,
+) -> OperationNode<'a, Out, (X,), Spread<F>> {
+    OperationNode::new((arg,), Spread(f))
 }
 
-// /// Helper for easier creating of new node for unary operation
-// pub fn new_unary<'a, T: 'static + Copy, X: Compute<'a>>(
-//     arg: X,
-//     f: impl 'static + Fn(T) -> T,
-// ) -> OperationNode<'a, T, ((X,), impl 'static + Fn((T,)) -> T)> {
-//     OperationNode::new(((arg,), move |(x,)| f(x)))
-// }
-//
-// /// Helper for easier creating of new node for binary operation
-// pub fn new_binary<'a, T: 'static + Copy>(
-//     arg1: impl Compute<'a, Output = T>,
-//     arg2: impl Compute<'a, Output = T>,
-//     f: impl 'a + Fn(T, T) -> T,
-// ) -> impl Compute<'a, Output = T> {
-//     OperationNode::new(((arg1, arg2), move |(x1, x2)| f(x1, x2)))
-// }
+/// Helper for easier creating of new node for binary operation
+pub fn new_binary<'a, X1, X2, Out, F>(
+    arg1: X1,
+    arg2: X2,
+    f: F,
+) -> OperationNode<'a, Out, (X1, X2), Spread<F>>
+where
+    X1: Compute<'a>,
+    X2: Compute<'a>,
+    Out: 'static + Copy,
+    F: Fn(X1::Output, X2::Output) -> Out,
+{
+    OperationNode::new((arg1, arg2), Spread(f))
+}
+
+/// Helper for creating of new node with any supported number of arguments.
+///
+/// Unlike [`OperationNode::new`] types of `f` arguments are inferred from `args`.
+pub fn new_nary<'a, Args: NodeArgs<'a>, Out: 'static + Copy, F: Fn(Args::Output) -> Out>(
+    args: Args,
+    f: F,
+) -> OperationNode<'a, Out, Args, F> {
+    OperationNode::new(args, f)
+}
 
 /// Noop operation to indicate input node
 pub struct InputOp(Cow<'static, str>);
 
-impl<'a, T: Copy + 'static, Arg, Op> Cached for OperationNode<'a, T, Arg, Op> {
-    fn invalidate_cache(&self) {
-        self.cache.set(None);
+/// Operation of nodes created by [`ConstantNode::new_constant`] and [`ConstantNode::new_parameter`]
+pub struct ConstantOp<T> {
+    name: Option<Cow<'static, str>>,
+    value: Cell<T>,
+}
+
+impl<T: Copy + 'static, Arg, Op> OperationNode<'_, T, Arg, Op> {
+    /// Discards cached values of this node and its dependents, `counted` unless it is a source
+    fn invalidate(&self, counted: bool) {
+        // operation is unknown here, so it can't be named
+        if self.cache.take().is_some() {
+            if counted {
+                self.observe(TraceEventKind::CacheInvalidated, None);
+            } else {
+                self.observe_source(TraceEventKind::CacheInvalidated, None);
+            }
+        }
         for x in self.dependents.borrow().iter() {
             (**x).invalidate_cache();
         }
     }
 }
 
+impl<'a, T: Copy + 'static, Arg, Op> Cached for OperationNode<'a, T, Arg, Op> {
+    fn invalidate_cache(&self) {
+        self.invalidate(true);
+    }
+}
+
 impl<'a, T: Copy + 'static> Compute<'a> for OperationNode<'a, T, (), InputOp> {
     type Output = T;
 
     fn compute(&self) -> Self::Output {
-        self.cache
+        let value = self
+            .cache
             .clone()
             .into_inner()
-            .expect("input should have been set at this point")
+            .expect("input should have been set at this point");
+        self.observe_source(TraceEventKind::CacheHit, self.name());
+        value
+    }
+
+    fn notify_deps(&'a self, dependent: &'a dyn Cached) {
+        self.add_dependent(dependent);
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(&self.operation.0)
+    }
+}
+
+impl<'a, T: Copy + 'static> Compute<'a> for OperationNode<'a, T, (), ConstantOp<T>> {
+    type Output = T;
+
+    fn compute(&self) -> Self::Output {
+        self.observe_source(TraceEventKind::CacheHit, self.name());
+        self.operation.value.get()
+    }
+
+    fn notify_deps(&'a self, dependent: &'a dyn Cached) {
+        self.add_dependent(dependent);
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.operation.name.as_deref()
+    }
+}
+
+/// Arguments of a node, implemented for tuples and arrays of nodes with statically known types
+pub trait NodeArgs<'a> {
+    /// Tuple of computed values of arguments
+    type Output;
+    fn compute(&self) -> Self::Output;
+    fn notify_deps(&'a self, dependent: &'a dyn Cached);
+}
+
+/// Operation of a node, which computes the arguments it needs
+pub trait NodeOp<'a, Args: NodeArgs<'a>, T> {
+    fn call(&self, args: &Args) -> T;
+
+    /// Name of the operation for printing, if it is known
+    fn name(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl<'a, Args: NodeArgs<'a>, T, F: Fn(Args::Output) -> T> NodeOp<'a, Args, T> for F {
+    fn call(&self, args: &Args) -> T {
+        self(args.compute())
+    }
+}
+
+/// Operation passing arguments to the function separately instead of as a tuple
+pub struct Spread<F>(pub F);
+
+impl<'a, T: 'static + Copy, Args: NodeArgs<'a>, Op> Compute<'a> for OperationNode<'a, T, Args, Op>
+where
+    Op: 'a + NodeOp<'a, Args, T>,
+{
+    type Output = T;
+
+    fn compute(&self) -> Self::Output {
+        if let Some(cached) = self.cached() {
+            self.observe(TraceEventKind::CacheHit, self.name());
+            return cached;
+        }
+        let updated = self.operation.call(&self.args);
+        self.cache.set(Some(updated));
+        self.observe(TraceEventKind::NodeComputed, self.name());
+        updated
     }
 
     fn notify_deps(&'a self, dependent: &'a dyn Cached) {
-        println!("add dep for input {} {:p}", self.operation.0, dependent);
-        self.dependents.borrow_mut().push(dependent);
+        self.add_dependent(dependent);
+        // args of shared nodes are wired only on the first visit
+        if !self.wired.replace(true) {
+            self.args.notify_deps(self);
+        }
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.operation.name()
     }
 }
 
-/// Implements [`Operation`] for multiple statically known inputs
+/// Implements [`NodeArgs`] and [`Spread`] for multiple statically known inputs
 macro_rules! impl_tuples {
     ($token:ident $id:tt $($tail:tt)*) => {
         impl_tuple!{ $token $id $($tail)* }
@@ -108,50 +315,194 @@ macro_rules! impl_tuples {
 
 macro_rules! impl_tuple {
     ($($generics:ident $ids:tt)+) => {
-        impl<'a,T:'static + Copy,$($generics : Compute<'a> ),+,F,> Compute<'a> for OperationNode<'a,T, ($($generics,)+) , F>
-        where
-            F: 'a + Fn(( $($generics :: Output ,)+ )) -> T
-        {
-            type Output = T;
+        impl<'a, $($generics : Compute<'a>),+> NodeArgs<'a> for ($($generics,)+) {
+            type Output = ($($generics::Output,)+);
 
             fn compute(&self) -> Self::Output {
-                if let Some(cached) = self.cached(){
-                    return cached;
-                }
-                let updated = (self.operation)( reverse!( self [$($ids)+]  ) );
-                self.cache.set(Some(updated));
-                updated
+                reverse!( self [$($ids)+] )
             }
 
-            fn notify_deps(&'a self, dependent: &'a dyn Cached){
-                self.dependents.borrow_mut().push(dependent);
+            fn notify_deps(&'a self, dependent: &'a dyn Cached) {
                 $(
-                    self.args.$ids.notify_deps(self);
+                    self.$ids.notify_deps(dependent);
                 )+
             }
+        }
 
+        impl<'a, $($generics : Compute<'a>),+, T, F> NodeOp<'a, ($($generics,)+), T> for Spread<F>
+        where
+            F: Fn($($generics::Output),+) -> T
+        {
+            #[allow(non_snake_case)]
+            fn call(&self, args: &($($generics,)+)) -> T {
+                let ($($generics,)+) = args.compute();
+                (self.0)($($generics),+)
+            }
         }
     };
 }
 
 macro_rules! reverse {
     ($self:ident [] $($reversed:tt)*) => {
-        ( $($self.args.$reversed.compute(),)*)
+        ( $($self.$reversed.compute(),)*)
     };
     ($self:ident [$first:tt $($rest:tt)*] $($reversed:tt)*) => {
         reverse!($self [$($rest)*] $first $($reversed)*)
     };
 }
 
-impl_tuples!(D 3 C 2 B 1 A 0);
+impl_tuples!(A11 11 A10 10 A9 9 A8 8 A7 7 A6 6 A5 5 A4 4 A3 3 A2 2 A1 1 A0 0);
+
+impl<'a, X: Compute<'a>, const N: usize> NodeArgs<'a> for [X; N] {
+    type Output = [X::Output; N];
+
+    fn compute(&self) -> Self::Output {
+        self.each_ref().map(|x| x.compute())
+    }
+
+    fn notify_deps(&'a self, dependent: &'a dyn Cached) {
+        for x in self {
+            x.notify_deps(dependent);
+        }
+    }
+}
+
+/// Implements [`NodeOp`] for [`BuiltinOp`] applied to statically known inputs of the same type
+macro_rules! impl_builtin_tuple {
+    ($($generics:ident $ids:tt)+) => {
+        impl<'a, T: Scalar, $($generics: Compute<'a, Output = T>),+> NodeOp<'a, ($($generics,)+), T> for BuiltinOp {
+            fn call(&self, args: &($($generics,)+)) -> T {
+                self.apply(&[$(args.$ids.compute()),+])
+            }
+
+            fn name(&self) -> Option<&str> {
+                Some(BuiltinOp::name(*self))
+            }
+        }
+    };
+}
+
+impl_builtin_tuple!(A 0);
+impl_builtin_tuple!(A 0 B 1);
+
+// only the chosen branch of select is computed
+impl<'a, T: Scalar, A, B, C> NodeOp<'a, (A, B, C), T> for BuiltinOp
+where
+    A: Compute<'a, Output = T>,
+    B: Compute<'a, Output = T>,
+    C: Compute<'a, Output = T>,
+{
+    fn call(&self, args: &(A, B, C)) -> T {
+        if *self == BuiltinOp::Select {
+            return if args.0.compute() != T::ZERO {
+                args.1.compute()
+            } else {
+                args.2.compute()
+            };
+        }
+        self.apply(&[args.0.compute(), args.1.compute(), args.2.compute()])
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(BuiltinOp::name(*self))
+    }
+}
+
+/// Creates node computing `if_true` or `if_false` depending on `condition`.
+///
+/// Only the chosen branch is computed, but the node is wired as a dependent of both,
+/// as references to dependents can't be added after [`Compute::create_reverse_deps`],
+/// see the [module docs](self).
+pub fn new_select<'a, T, C, A, B>(
+    condition: C,
+    if_true: A,
+    if_false: B,
+) -> OperationNode<'a, T, (C, A, B), SelectOp>
+where
+    C: Compute<'a, Output = bool>,
+    A: Compute<'a, Output = T>,
+    B: Compute<'a, Output = T>,
+{
+    OperationNode::new((condition, if_true, if_false), SelectOp)
+}
+
+/// Operation of nodes created by [`new_select`]
+pub struct SelectOp;
+
+impl<'a, T, C, A, B> NodeOp<'a, (C, A, B), T> for SelectOp
+where
+    C: Compute<'a, Output = bool>,
+    A: Compute<'a, Output = T>,
+    B: Compute<'a, Output = T>,
+{
+    fn call(&self, args: &(C, A, B)) -> T {
+        if args.0.compute() {
+            args.1.compute()
+        } else {
+            args.2.compute()
+        }
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some("select")
+    }
+}
+
+/// Helpers creating nodes for each [`BuiltinOp`]
+pub mod builtins {
+    use super::*;
+
+    macro_rules! builtin_fns {
+        ($($name:ident $op:ident ($($args:ident $generics:ident),+);)+) => {$(
+            #[doc = concat!("Creates new node applying [`BuiltinOp::", stringify!($op), "`]")]
+            pub fn $name<'a, T: Scalar, $($generics: Compute<'a, Output = T>),+>(
+                $($args: $generics),+
+            ) -> OperationNode<'a, T, ($($generics,)+), BuiltinOp> {
+                OperationNode::new(($($args,)+), BuiltinOp::$op)
+            }
+        )+};
+    }
+
+    builtin_fns! {
+        add Add(x A, y B);
+        sub Sub(x A, y B);
+        mul Mul(x A, y B);
+        div Div(x A, y B);
+        neg Neg(x A);
+        sin Sin(x A);
+        cos Cos(x A);
+        tan Tan(x A);
+        exp Exp(x A);
+        ln Ln(x A);
+        sqrt Sqrt(x A);
+        pow Pow(x A, y B);
+        min Min(x A, y B);
+        max Max(x A, y B);
+        abs Abs(x A);
+        clamp Clamp(x A, low B, high C);
+        lt Lt(x A, y B);
+        le Le(x A, y B);
+        gt Gt(x A, y B);
+        ge Ge(x A, y B);
+        eq Eq(x A, y B);
+        ne Ne(x A, y B);
+        select Select(condition A, if_true B, if_false C);
+    }
+}
 
 pub trait Compute<'a>: Cached {
     type Output;
     fn compute(&self) -> Self::Output;
     fn notify_deps(&'a self, dependent: &'a dyn Cached);
+
+    /// Name of the operation for printing, if it is known
+    fn name(&self) -> Option<&str> {
+        None
+    }
     // fn collect_inputs(&'a self, inputs: &mut InputsMap<'a, Self::Output>);
 
-    /// must be called before all operations on the graph start
+    /// must be called before all operations on the graph start,
+    /// calling it again or for several roots sharing nodes is a noop for already wired nodes
     fn create_reverse_deps(&'a self)
     where
         Self: Sized,
@@ -183,8 +534,13 @@ impl<'a, T: Compute<'a>> Compute<'a> for &'a T {
     fn notify_deps(&'a self, dependent: &'a dyn Cached) {
         (**self).notify_deps(dependent)
     }
+
+    fn name(&self) -> Option<&str> {
+        (**self).name()
+    }
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
 
@@ -193,8 +549,7 @@ mod tests {
         let x1 = InputNode::new_input("x1");
         x1.set(1);
 
-        // let result = new_unary(&x1, |x: i32| x.pow(2));
-        let result = OperationNode::new((&x1,), move |(x,): (i32,)| x.pow(2));
+        let result = new_unary(&x1, |x: i32| x.pow(2));
         result.create_reverse_deps();
 
         assert_eq!(result.compute(), 1);
@@ -215,6 +570,117 @@ mod tests {
         assert_eq!(result.compute(), 2.25f32);
     }
 
+    #[test]
+    fn test_helpers() {
+        type Pow<'a, X> =
+            OperationNode<'a, f32, (X, Literal<'a, f32>), Spread<fn(f32, f32) -> f32>>;
+
+        fn pow_f32<'a, X: Compute<'a, Output = f32>>(arg: X, n: f32) -> Pow<'a, X> {
+            new_binary(arg, Literal::new(n), f32::powf)
+        }
+
+        let x1 = InputNode::new_input("x1");
+        let x2 = InputNode::new_input("x2");
+        let x3 = InputNode::new_input("x3");
+        x1.set(2.0f32);
+        x2.set(3i32);
+        x3.set(true);
+
+        let pow = pow_f32(&x1, 3.0);
+        let sum = new_binary(&pow, &x2, |x, n| x + n as f32);
+        let result = new_nary((&sum, &x2, &x3), |(sum, n, negate)| {
+            if negate {
+                -sum * n as f32
+            } else {
+                sum * n as f32
+            }
+        });
+        result.create_reverse_deps();
+        assert_eq!(result.compute(), -33.0);
+        x3.set(false);
+        assert_eq!(result.compute(), 33.0);
+        x1.set(1.0);
+        assert_eq!(result.compute(), 12.0);
+    }
+
+    #[test]
+    fn test_wide() {
+        let inputs: [_; 12] = std::array::from_fn(|x| {
+            let input = InputNode::new_input(x.to_string());
+            input.set(x as i32);
+            input
+        });
+        let [x0, x1, x2, x3, x4, x5, x6, x7, x8, x9, x10, x11] = &inputs;
+
+        let tuple = new_nary((x0, x1, x2, x3, x4, x5, x6, x7, x8, x9, x10, x11), |x| {
+            x.0 + x.11
+        });
+        let array = new_nary(inputs.each_ref(), |x| x.iter().sum::<i32>());
+        tuple.create_reverse_deps();
+        array.create_reverse_deps();
+        assert_eq!(tuple.compute(), 11);
+        assert_eq!(array.compute(), 66);
+
+        inputs[11].set(0);
+        assert_eq!(tuple.compute(), 0);
+        assert_eq!(array.compute(), 55);
+    }
+
+    #[test]
+    fn test_diamond() {
+        let x1 = InputNode::new_input("x1");
+        x1.set(1);
+
+        let left = new_unary(&x1, |x: i32| x + 1);
+        let right = new_unary(&x1, |x: i32| x * 2);
+        let result = new_nary((&left, &right, &left), |(a, b, c)| a + b + c);
+        result.create_reverse_deps();
+        result.create_reverse_deps();
+        assert_eq!(x1.dependents.borrow().len(), 2);
+        assert_eq!(left.dependents.borrow().len(), 1);
+        assert_eq!(right.dependents.borrow().len(), 1);
+
+        // another root sharing nodes with the first one
+        let other = new_binary(&left, &result, |a: i32, b: i32| a * b);
+        other.create_reverse_deps();
+        assert_eq!(x1.dependents.borrow().len(), 2);
+        assert_eq!(left.dependents.borrow().len(), 2);
+        assert_eq!(result.dependents.borrow().len(), 2);
+
+        assert_eq!(other.compute(), 2 * 6);
+        x1.set(2);
+        assert_eq!(result.cached(), None);
+        assert_eq!(other.cached(), None);
+        assert_eq!(other.compute(), 3 * 10);
+    }
+
+    #[test]
+    fn test_select() {
+        let x = InputNode::new_input("x");
+        let y = InputNode::new_input("y");
+        x.set(1.0f32);
+        y.set(2.0f32);
+        let calls = Cell::new(0);
+        let expensive = new_unary(&y, |y: f32| {
+            calls.set(calls.get() + 1);
+            y * 100.0
+        });
+        let condition = new_unary(&x, |x: f32| x > 0.0);
+        let result = new_select(&condition, &x, &expensive);
+        let builtin = builtins::select(builtins::gt(&x, Literal::new(0.0)), &x, &expensive);
+        result.create_reverse_deps();
+        builtin.create_reverse_deps();
+
+        assert_eq!(result.compute(), 1.0);
+        assert_eq!(builtin.compute(), 1.0);
+        assert_eq!(calls.get(), 0);
+
+        x.set(-1.0);
+        assert_eq!(result.compute(), 200.0);
+        assert_eq!(builtin.compute(), 200.0);
+        assert_eq!(calls.get(), 1);
+    }
+
     #[test]
     fn test_cache() {
         let x1 = InputNode::new_input("x1");
@@ -250,4 +716,41 @@ mod tests {
         assert_eq!(node2.cached(), Some(4.0f32));
         assert_eq!(node3.cached(), None);
     }
+
+    #[test]
+    fn test_builtins() {
+        let x1 = InputNode::new_input("x1");
+        let x2 = InputNode::new_input("x2");
+        x1.set(2.0f32);
+        x2.set(3.0f32);
+
+        let result = builtins::mul(&x1, builtins::neg(&x2));
+        result.create_reverse_deps();
+        assert_eq!(result.name(), Some("mul"));
+        assert_eq!(x1.name(), Some("x1"));
+        assert_eq!(result.compute(), -6.0);
+        x2.set(1.0);
+        assert_eq!(result.cached(), None);
+        assert_eq!(result.compute(), -2.0);
+    }
+
+    #[test]
+    fn test_constants() {
+        let x = InputNode::new_input("x");
+        let exponent = ConstantNode::new_constant(2.0f32);
+        let offset = ConstantNode::new_parameter("offset", 1.0);
+        x.set(3.0);
+        let pow = builtins::pow(&x, &exponent);
+        let result = builtins::add(&pow, &offset);
+        result.create_reverse_deps();
+        assert_eq!(result.compute(), 10.0);
+        assert_eq!(offset.name(), Some("offset"));
+
+        offset.set(-1.0);
+        assert_eq!(pow.cached(), Some(9.0));
+        assert_eq!(result.compute(), 8.0);
+        exponent.set(3.0);
+        assert_eq!(result.cached(), None);
+        assert_eq!(result.compute(), 26.0);
+    }
 }
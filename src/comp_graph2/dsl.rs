@@ -0,0 +1,290 @@
+//! Operator overloading and [`graph!`](crate::graph) macro
+//! for declarative building of graphs from builtin operations.
+
+use super::{builtins, Cached, Compute, OperationNode};
+use crate::ops::{BuiltinOp, Scalar};
+use std::marker::PhantomData;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// Declares nodes of a graph with infix expressions and wires their reverse dependencies.
+///
+/// ```
+/// # use comp_graph::graph;
+/// # use comp_graph::comp_graph2::*;
+/// let x1 = InputNode::new_input("x1");
+/// let x2 = InputNode::new_input("x2");
+/// let x3 = InputNode::new_input("x3");
+/// graph! {
+///     y = x1 + x2 * sin(x2 + x3.powf(3.0));
+/// }
+/// x1.set(1f32);
+/// x2.set(2f32);
+/// x3.set(3f32);
+/// assert_eq!(y.compute(), 1.0 + 2.0 * (2f32 + 27.0).sin());
+/// ```
+///
+/// Every statement `name = expression;` declares a new local node `name`.
+/// Variables in expressions are used by reference, so they can be inputs
+/// as well as nodes declared by previous statements,
+/// function and method calls are mapped to [`builtins`](crate::comp_graph2::builtins)
+/// and literals become [`Literal`]s.
+#[macro_export]
+macro_rules! graph {
+    (@statements [$($roots:ident)*]) => {
+        $( $crate::comp_graph2::Compute::create_reverse_deps(&$roots); )*
+    };
+    (@statements [$($roots:ident)*] $name:ident = $($rest:tt)+) => {
+        $crate::graph!(@statement [$($roots)*] $name [] $($rest)+)
+    };
+    (@statement [$($roots:ident)*] $name:ident [$($expr:tt)+] $(; $($rest:tt)*)?) => {
+        let $name = {
+            #[allow(unused_imports)]
+            use $crate::comp_graph2::ComputeExt as _;
+            $crate::graph!(@expr [] $($expr)+)
+        };
+        $crate::graph!(@statements [$($roots)* $name] $($($rest)*)?)
+    };
+    (@statement [$($roots:ident)*] $name:ident [$($expr:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::graph!(@statement [$($roots)*] $name [$($expr)* $next] $($rest)*)
+    };
+
+    (@expr [$($out:tt)*]) => {
+        $($out)*
+    };
+    (@expr [$($out:tt)*] . $method:ident ( $($args:tt)* ) $($rest:tt)*) => {
+        $crate::graph!(@args [$($out)* . $method] [$($rest)*] [] [] $($args)*)
+    };
+    (@expr [$($out:tt)*] $function:ident ( $($args:tt)* ) $($rest:tt)*) => {
+        $crate::graph!(
+            @args [$($out)* $crate::comp_graph2::builtins::$function] [$($rest)*] [] [] $($args)*
+        )
+    };
+    // otherwise `literal` fragment would try to parse a negative literal
+    (@expr [$($out:tt)*] - $($rest:tt)*) => {
+        $crate::graph!(@expr [$($out)* -] $($rest)*)
+    };
+    (@expr [$($out:tt)*] $value:literal $($rest:tt)*) => {
+        $crate::graph!(@expr [$($out)* $crate::comp_graph2::Literal::new($value)] $($rest)*)
+    };
+    (@expr [$($out:tt)*] $variable:ident $($rest:tt)*) => {
+        $crate::graph!(@expr [$($out)* (&$variable)] $($rest)*)
+    };
+    (@expr [$($out:tt)*] ( $($inner:tt)* ) $($rest:tt)*) => {
+        $crate::graph!(@expr [$($out)* ($crate::graph!(@expr [] $($inner)*))] $($rest)*)
+    };
+    (@expr [$($out:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::graph!(@expr [$($out)* $next] $($rest)*)
+    };
+
+    // splits arguments of a call by commas and converts each one separately
+    (@args [$($out:tt)*] [$($rest:tt)*] [$($done:tt)*] [$($arg:tt)+] , $($args:tt)*) => {
+        $crate::graph!(
+            @args [$($out)*] [$($rest)*] [$($done)* $crate::graph!(@expr [] $($arg)+),] [] $($args)*
+        )
+    };
+    (@args [$($out:tt)*] [$($rest:tt)*] [$($done:tt)*] [$($arg:tt)*] $next:tt $($args:tt)*) => {
+        $crate::graph!(@args [$($out)*] [$($rest)*] [$($done)*] [$($arg)* $next] $($args)*)
+    };
+    (@args [$($out:tt)*] [$($rest:tt)*] [$($done:tt)*] [$($arg:tt)+]) => {
+        $crate::graph!(@expr [$($out)* ($($done)* $crate::graph!(@expr [] $($arg)+))] $($rest)*)
+    };
+    (@args [$($out:tt)*] [$($rest:tt)*] [$($done:tt)*] []) => {
+        $crate::graph!(@expr [$($out)* ($($done)*)] $($rest)*)
+    };
+
+    ($($statements:tt)+) => {
+        $crate::graph!(@statements [] $($statements)+)
+    };
+}
+
+macro_rules! compute_ext {
+    ($($unary:ident)+) => {
+        /// Methods applying builtin operations to nodes, so they can be chained like `x.powf(3.0)`
+        pub trait ComputeExt<'a>: Compute<'a> + Sized {
+            $(
+                fn $unary(self) -> OperationNode<'a, Self::Output, (Self,), BuiltinOp>
+                where
+                    Self::Output: Scalar,
+                {
+                    builtins::$unary(self)
+                }
+            )+
+
+            fn powf<R: Compute<'a, Output = Self::Output>>(
+                self,
+                n: R,
+            ) -> OperationNode<'a, Self::Output, (Self, R), BuiltinOp>
+            where
+                Self::Output: Scalar,
+            {
+                builtins::pow(self, n)
+            }
+
+            fn min<R: Compute<'a, Output = Self::Output>>(
+                self,
+                other: R,
+            ) -> OperationNode<'a, Self::Output, (Self, R), BuiltinOp>
+            where
+                Self::Output: Scalar,
+            {
+                builtins::min(self, other)
+            }
+
+            fn max<R: Compute<'a, Output = Self::Output>>(
+                self,
+                other: R,
+            ) -> OperationNode<'a, Self::Output, (Self, R), BuiltinOp>
+            where
+                Self::Output: Scalar,
+            {
+                builtins::max(self, other)
+            }
+
+            fn clamp<L, H>(
+                self,
+                low: L,
+                high: H,
+            ) -> OperationNode<'a, Self::Output, (Self, L, H), BuiltinOp>
+            where
+                Self::Output: Scalar,
+                L: Compute<'a, Output = Self::Output>,
+                H: Compute<'a, Output = Self::Output>,
+            {
+                builtins::clamp(self, low, high)
+            }
+        }
+    };
+}
+
+compute_ext!(sin cos tan exp ln sqrt abs);
+
+impl<'a, X: Compute<'a>> ComputeExt<'a> for X {}
+
+/// Constant value that can be used as an argument of nodes
+///
+/// Carries the lifetime of nodes it's combined with, so operators can be implemented for it.
+#[derive(Copy, Clone, Debug)]
+pub struct Literal<'a, T>(T, PhantomData<&'a ()>);
+
+impl<'a, T> Literal<'a, T> {
+    pub fn new(value: T) -> Self {
+        Literal(value, PhantomData)
+    }
+}
+
+impl<'a, T> Cached for Literal<'a, T> {
+    fn invalidate_cache(&self) {}
+}
+
+impl<'a, T: Copy> Compute<'a> for Literal<'a, T> {
+    type Output = T;
+
+    fn compute(&self) -> Self::Output {
+        self.0
+    }
+
+    fn notify_deps(&'a self, _dependent: &'a dyn Cached) {}
+}
+
+macro_rules! impl_operators {
+    ($($operator:ident $method:ident $op:ident;)+) => {$(
+        impl<'a, T: Scalar, Args, Op, R> $operator<R> for OperationNode<'a, T, Args, Op>
+        where
+            Self: Compute<'a, Output = T>,
+            R: Compute<'a, Output = T>,
+        {
+            type Output = OperationNode<'a, T, (Self, R), BuiltinOp>;
+
+            fn $method(self, rhs: R) -> Self::Output {
+                OperationNode::new((self, rhs), BuiltinOp::$op)
+            }
+        }
+
+        impl<'a, T: Scalar, Args, Op, R> $operator<R> for &'a OperationNode<'a, T, Args, Op>
+        where
+            OperationNode<'a, T, Args, Op>: Compute<'a, Output = T>,
+            R: Compute<'a, Output = T>,
+        {
+            type Output = OperationNode<'a, T, (Self, R), BuiltinOp>;
+
+            fn $method(self, rhs: R) -> Self::Output {
+                OperationNode::new((self, rhs), BuiltinOp::$op)
+            }
+        }
+
+        impl<'a, T: Scalar, R> $operator<R> for Literal<'a, T>
+        where
+            R: Compute<'a, Output = T>,
+        {
+            type Output = OperationNode<'a, T, (Self, R), BuiltinOp>;
+
+            fn $method(self, rhs: R) -> Self::Output {
+                OperationNode::new((self, rhs), BuiltinOp::$op)
+            }
+        }
+    )+};
+}
+
+impl_operators! {
+    Add add Add;
+    Sub sub Sub;
+    Mul mul Mul;
+    Div div Div;
+}
+
+impl<'a, T: Scalar, Args, Op> Neg for OperationNode<'a, T, Args, Op>
+where
+    Self: Compute<'a, Output = T>,
+{
+    type Output = OperationNode<'a, T, (Self,), BuiltinOp>;
+
+    fn neg(self) -> Self::Output {
+        OperationNode::new((self,), BuiltinOp::Neg)
+    }
+}
+
+impl<'a, T: Scalar, Args, Op> Neg for &'a OperationNode<'a, T, Args, Op>
+where
+    OperationNode<'a, T, Args, Op>: Compute<'a, Output = T>,
+{
+    type Output = OperationNode<'a, T, (Self,), BuiltinOp>;
+
+    fn neg(self) -> Self::Output {
+        OperationNode::new((self,), BuiltinOp::Neg)
+    }
+}
+
+impl<'a, T: Scalar> Neg for Literal<'a, T> {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Literal::new(-self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::comp_graph2::*;
+
+    #[test]
+    fn test_graph_macro() {
+        let x1 = InputNode::new_input("x1");
+        let x2 = InputNode::new_input("x2");
+        let x3 = InputNode::new_input("x3");
+        crate::graph! {
+            y = x1 + x2 * sin(x2 + x3.powf(3.0));
+            z = -(y - 1.0) / 2.0 + max(x1, x3.abs());
+        }
+        x1.set(1f32);
+        x2.set(2f32);
+        x3.set(3f32);
+        let expected_y = 1.0 + 2.0 * (2f32 + 27.0).sin();
+        assert_eq!(y.compute(), expected_y);
+        assert_eq!(z.compute(), -(expected_y - 1.0) / 2.0 + 3.0);
+
+        x1.set(2f32);
+        x2.set(3f32);
+        x3.set(4f32);
+        assert_eq!(y.compute(), 2.0 + 3.0 * (3f32 + 64.0).sin());
+    }
+}
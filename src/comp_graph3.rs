@@ -1,24 +1,120 @@
-use smallvec::SmallVec;
+use crate::ops::{BuiltinOp, Scalar};
+use crate::stats::CacheStats;
+use crate::trace::{TraceEvent, TraceEventKind, Tracer};
+use profile::Profiler;
+use smallvec::{smallvec, SmallVec};
+use stateful::Simulation;
 use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
 use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::Rc;
+
+mod binary;
+mod bytecode;
+mod codegen;
+mod diff;
+mod dot;
+mod fixed_point;
+mod introspect;
+mod json;
+mod multi_output;
+mod profile;
+mod registry;
+mod rewire;
+mod stateful;
+mod stream;
+mod subgraph;
+
+pub use binary::BinaryError;
+pub use bytecode::{CompileError, Program};
+pub use diff::DiffError;
+pub use fixed_point::{EvalError, LoopOptions};
+pub use introspect::NodeRef;
+pub use json::JsonError;
+pub use profile::{NodeProfile, Profile};
+pub use registry::OpRegistry;
+pub use rewire::RewireError;
+pub use stream::{Sample, Stream};
+pub use subgraph::SubGraph;
 
 #[derive(Default)]
 pub struct CompGraph<T> {
     nodes: Vec<Node<T>>,
     graph_inputs: HashMap<Cow<'static, str>, usize>,
+    observers: Observers,
+    // errors of the evaluation in progress, reported by operations of the nodes
+    eval_errors: Rc<RefCell<Vec<EvalError>>>,
+    simulation: Simulation<T>,
+    // subgraphs of nodes added by `call`, so they can be inlined later
+    calls: HashMap<usize, SubGraph<T>>,
 }
 
+/// Optional hooks notified about evaluation of nodes
+#[derive(Default)]
+struct Observers {
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+}
+
+type BoxedOp<T> = Box<dyn FnMut(&mut dyn Iterator<Item = T>) -> T>;
+type BoxedMultiOp<T> = Box<dyn FnMut(&mut dyn Iterator<Item = T>) -> Vec<T>>;
+
 // using SmallVec to optimize for binary and unary operations
 struct Node<T> {
     cache: Option<T>,
     node_inputs: SmallVec<[NodeId; 2]>,
     dependents: SmallVec<[usize; 2]>,
-    op: Box<dyn FnMut(&mut dyn Iterator<Item = T>) -> T>,
+    stats: CacheStats,
+    kind: OpKind,
+    label: Option<Cow<'static, str>>,
+    op: NodeOp<T>,
+}
+
+/// How the value of a node is computed
+enum NodeOp<T> {
+    /// Inputs and constants are set from outside, ports are read from their source
+    None,
+    Single(BoxedOp<T>),
+    /// Operation producing `count` values, read by [`OpKind::Port`] nodes,
+    /// `outputs` are the values of the last run
+    Multi {
+        op: BoxedMultiOp<T>,
+        count: usize,
+        outputs: Vec<T>,
+    },
+}
+
+/// What the operation of a node is, as far as the graph can tell
+#[derive(Clone, Debug, PartialEq)]
+pub enum OpKind {
+    Input(Cow<'static, str>),
+    /// Value stored in the graph, named if it was added as a parameter
+    Constant(Option<Cow<'static, str>>),
+    Builtin(BuiltinOp),
+    /// Closure added under a name, which can be looked up in [`OpRegistry`]
+    Named(Cow<'static, str>),
+    /// Arbitrary closure
+    Custom,
+    /// Output `index` of the node with several outputs that is its only input
+    Port(usize),
+}
+
+impl OpKind {
+    /// Name of the input or the operation, if it has one
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            OpKind::Input(name) | OpKind::Named(name) => Some(name),
+            OpKind::Constant(name) => name.as_deref(),
+            OpKind::Builtin(op) => Some(op.name()),
+            OpKind::Custom | OpKind::Port(_) => None,
+        }
+    }
 }
 
 // for type safety
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct NodeId(usize);
 
 impl<T> CompGraph<T> {
@@ -26,15 +122,141 @@ impl<T> CompGraph<T> {
         Self {
             nodes: vec![],
             graph_inputs: Default::default(),
+            observers: Default::default(),
+            eval_errors: Default::default(),
+            simulation: Default::default(),
+            calls: Default::default(),
+        }
+    }
+
+    /// Sets callback receiving [`TraceEvent`]s of this graph, returning the previous one
+    pub fn set_tracer(&mut self, tracer: impl 'static + FnMut(&TraceEvent)) -> Option<Tracer> {
+        self.observers.tracer.replace(Box::new(tracer))
+    }
+
+    pub fn clear_tracer(&mut self) -> Option<Tracer> {
+        self.observers.tracer.take()
+    }
+
+    /// Cache counters summed over all nodes
+    pub fn cache_stats(&self) -> CacheStats {
+        self.nodes
+            .iter()
+            .map(|node| node.stats)
+            .fold(CacheStats::default(), |a, b| a + b)
+    }
+
+    /// Cache counters of `node`
+    pub fn node_cache_stats(&self, node: NodeId) -> CacheStats {
+        self.nodes[node.0].stats
+    }
+
+    pub fn reset_cache_stats(&mut self) {
+        for node in &mut self.nodes {
+            node.stats = CacheStats::default();
         }
     }
 
+    fn observe(&mut self, kind: TraceEventKind, node: usize) {
+        self.observers.observe(kind, node, &mut self.nodes[node]);
+    }
+
     pub fn add_node(
         &mut self,
         inputs: impl IntoIterator<Item = NodeId>,
         op: impl 'static + FnMut(&mut dyn Iterator<Item = T>) -> T,
     ) -> NodeId {
-        let node_inputs: SmallVec<[NodeId; 2]> = inputs.into_iter().collect();
+        self.push_node(
+            inputs.into_iter().collect(),
+            OpKind::Custom,
+            NodeOp::Single(Box::new(op)),
+        )
+    }
+
+    /// Same as [`add_node`](Self::add_node) but remembers `name` of the operation
+    /// so the graph can be serialized and restored with an [`OpRegistry`].
+    ///
+    /// Panics if `name` is a name of a [`BuiltinOp`].
+    pub fn add_named_node(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        inputs: impl IntoIterator<Item = NodeId>,
+        op: impl 'static + FnMut(&mut dyn Iterator<Item = T>) -> T,
+    ) -> NodeId {
+        let name = name.into();
+        registry::assert_not_builtin(&name);
+        self.push_node(
+            inputs.into_iter().collect(),
+            OpKind::Named(name),
+            NodeOp::Single(Box::new(op)),
+        )
+    }
+
+    pub fn add_input_node(&mut self, name: impl Into<Cow<'static, str>>) -> NodeId {
+        let name = name.into();
+        let id = self.push_node(SmallVec::new(), OpKind::Input(name.clone()), NodeOp::None);
+        self.graph_inputs.insert(name, id.0);
+        id
+    }
+
+    /// Adds node with fixed `value`, which is not an input of the graph
+    /// but can still be changed with [`set_constant`](Self::set_constant)
+    pub fn add_constant(&mut self, value: T) -> NodeId {
+        self.push_constant(None, value)
+    }
+
+    /// Same as [`add_constant`](Self::add_constant) but named,
+    /// e.g. for coefficients of a model that are tuned rather than fed
+    pub fn add_parameter(&mut self, name: impl Into<Cow<'static, str>>, value: T) -> NodeId {
+        self.push_constant(Some(name.into()), value)
+    }
+
+    fn push_constant(&mut self, name: Option<Cow<'static, str>>, value: T) -> NodeId {
+        let id = self.push_node(SmallVec::new(), OpKind::Constant(name), NodeOp::None);
+        self.nodes[id.0].cache = Some(value);
+        id
+    }
+
+    /// Changes value of a constant or parameter `node`, invalidating its dependents
+    pub fn set_constant(&mut self, node: NodeId, value: T) {
+        assert!(
+            matches!(self.nodes[node.0].kind, OpKind::Constant(_)),
+            "{} is not a constant",
+            self.describe(node)
+        );
+        self.invalidate_node(node);
+        self.nodes[node.0].cache = Some(value);
+        self.observe(TraceEventKind::InputSet, node.0);
+    }
+
+    /// Returns what kind of operation `node` performs
+    pub fn op_kind(&self, node: NodeId) -> &OpKind {
+        &self.nodes[node.0].kind
+    }
+
+    /// Sets human-readable label of `node` shown in error messages, traces, DOT and debug output
+    pub fn set_label(&mut self, node: NodeId, label: impl Into<Cow<'static, str>>) {
+        self.nodes[node.0].label = Some(label.into());
+    }
+
+    pub fn label(&self, node: NodeId) -> Option<&str> {
+        self.nodes[node.0].label.as_deref()
+    }
+
+    /// Refers to `node` by its label if it has one, or by id otherwise
+    fn describe(&self, node: NodeId) -> String {
+        match &self.nodes[node.0].label {
+            Some(label) => format!("node {:?}", label),
+            None => format!("node {}", node.0),
+        }
+    }
+
+    fn push_node(
+        &mut self,
+        node_inputs: SmallVec<[NodeId; 2]>,
+        kind: OpKind,
+        op: NodeOp<T>,
+    ) -> NodeId {
         let next_id = self.nodes.len();
         for input in node_inputs.iter() {
             self.nodes[input.0].dependents.push(next_id)
@@ -43,70 +265,339 @@ impl<T> CompGraph<T> {
             cache: None,
             node_inputs,
             dependents: SmallVec::new(),
-            op: Box::new(op),
+            stats: CacheStats::default(),
+            kind,
+            label: None,
+            op,
         });
 
         NodeId(next_id)
     }
 
-    pub fn add_input_node(&mut self, name: impl Into<Cow<'static, str>>) -> NodeId {
-        let id = self.add_node(Vec::new(), |_| {
-            unreachable!("should not be called on input node")
-        });
-        self.graph_inputs.insert(name.into(), id.0);
-        id
+    /// Replaces inputs and operation of `node`, invalidating it and its dependents.
+    ///
+    /// Caller is responsible for not creating cycles.
+    pub(crate) fn rewire(
+        &mut self,
+        node: NodeId,
+        node_inputs: SmallVec<[NodeId; 2]>,
+        kind: OpKind,
+        op: impl 'static + FnMut(&mut dyn Iterator<Item = T>) -> T,
+    ) {
+        self.replace_inputs(node, node_inputs);
+        let current = &mut self.nodes[node.0];
+        current.kind = kind;
+        current.op = NodeOp::Single(Box::new(op));
+    }
+
+    /// Ids of `node` and all nodes it depends on, each one after its inputs
+    fn postorder(&self, node: NodeId) -> Vec<usize> {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut order = vec![];
+        self.visit_postorder(node.0, &mut visited, &mut order);
+        order
+    }
+
+    /// Appends `node` and nodes it depends on that aren't `visited` yet to `order`, each one after its inputs
+    fn visit_postorder(&self, node: usize, visited: &mut [bool], order: &mut Vec<usize>) {
+        let mut stack = vec![(node, false)];
+        while let Some((next, inputs_done)) = stack.pop() {
+            if inputs_done {
+                order.push(next);
+                continue;
+            }
+            if visited[next] {
+                continue;
+            }
+            visited[next] = true;
+            stack.push((next, true));
+            stack.extend(
+                self.nodes[next]
+                    .node_inputs
+                    .iter()
+                    .rev()
+                    .filter(|input| !visited[input.0])
+                    .map(|input| (input.0, false)),
+            );
+        }
     }
 
     pub fn set_input(&mut self, name: &str, data: T) {
         let input_id = *self.graph_inputs.get(name).expect("no such input");
         self.invalidate_node(NodeId(input_id));
         self.nodes[input_id].cache = Some(data);
+        self.observe(TraceEventKind::InputSet, input_id);
     }
 
     pub fn invalidate_node(&mut self, node: NodeId) {
         let mut stack = vec![Reverse(node.0)];
         while let Some(Reverse(next)) = stack.pop() {
-            self.nodes[next].cache = None;
+            let current = &mut self.nodes[next];
+            // constants have no operation to restore the value
+            let is_constant = matches!(current.kind, OpKind::Constant(_));
+            if !is_constant && current.cache.take().is_some() {
+                self.observe(TraceEventKind::CacheInvalidated, next);
+            }
             stack.extend(self.nodes[next].dependents.iter().map(|&x| Reverse(x)))
         }
     }
 }
+impl<T: Scalar> CompGraph<T> {
+    /// Adds node applying builtin operation `op` to `inputs`
+    pub fn add_op(&mut self, op: BuiltinOp, inputs: impl IntoIterator<Item = NodeId>) -> NodeId {
+        let node_inputs: SmallVec<[NodeId; 2]> = inputs.into_iter().collect();
+        assert_eq!(
+            node_inputs.len(),
+            op.arity(),
+            "wrong number of inputs for {}",
+            op
+        );
+        self.push_node(
+            node_inputs,
+            OpKind::Builtin(op),
+            NodeOp::Single(builtin_op(op)),
+        )
+    }
+}
+
+fn builtin_op<T: Scalar>(op: BuiltinOp) -> BoxedOp<T> {
+    Box::new(move |args| op.eval(args))
+}
+
 impl<T: Clone> CompGraph<T> {
     #[cfg(test)]
     fn cache(&self, node: NodeId) -> Option<T> {
         self.nodes[node.0].cache.clone()
     }
 
+    /// Computes `node`, panics if the evaluation fails, see [`try_compute`](Self::try_compute)
     pub fn compute(&mut self, node: NodeId) -> T {
-        let node = [node];
-        calculate_node(&mut self.nodes, &node, &mut |x| x.next().unwrap())
+        self.try_compute(node)
+            .unwrap_or_else(|err| panic!("{} failed: {}", self.describe(err.node()), err))
     }
-}
 
-fn calculate_node<T: Clone>(
-    head: &mut [Node<T>],
-    inputs: &[NodeId],
-    operation: &mut dyn FnMut(&mut dyn Iterator<Item = T>) -> T,
-) -> T {
-    for &NodeId(input) in inputs {
-        if head[input].cache.is_none() {
-            let (before, after) = head.split_at_mut(input);
-            let result = calculate_node(before, &after[0].node_inputs, &mut after[0].op);
-            head[input].cache = Some(result);
+    /// Computes `node`, returns error if some node it depends on failed
+    /// e.g. because its loop didn't converge.
+    ///
+    /// If several nodes failed, the error of the first one is returned.
+    /// Failed nodes are not cached, so they are evaluated again on the next request.
+    pub fn try_compute(&mut self, node: NodeId) -> Result<T, EvalError> {
+        let (value, errors) = self.compute_with_error(node);
+        match errors.into_iter().next() {
+            Some(err) => Err(err),
+            None => Ok(value),
         }
     }
-    let mut iter = inputs.iter().map(|input| {
-        head[input.0]
+
+    /// Computes `node` also when some nodes it depends on failed,
+    /// returning the value computed from whatever the failed nodes produced.
+    ///
+    /// If an operation panics, the panic is raised again naming its node.
+    fn compute_with_error(&mut self, node: NodeId) -> (T, Vec<EvalError>) {
+        let computed = panic::catch_unwind(AssertUnwindSafe(|| {
+            ensure_cached(&mut self.nodes, node.0, &mut self.observers)
+        }));
+        if let Err(payload) = computed {
+            self.eval_errors.take();
+            let failed = PANICKED_NODE.take();
+            let message = match (
+                payload.downcast_ref::<&str>(),
+                payload.downcast_ref::<String>(),
+            ) {
+                (Some(message), _) => message.to_string(),
+                (_, Some(message)) => message.clone(),
+                _ => panic::resume_unwind(payload),
+            };
+            match failed {
+                Some(failed) => panic!("{} failed: {}", self.describe(NodeId(failed)), message),
+                None => panic::resume_unwind(payload),
+            }
+        }
+        let value = self.nodes[node.0]
             .cache
             .clone()
-            .expect("should be set in above loop")
-    });
-    operation(&mut iter)
+            .expect("should be set by ensure_cached");
+        let errors = self.eval_errors.take();
+        for err in &errors {
+            self.invalidate_node(err.node());
+        }
+        (value, errors)
+    }
+}
+
+/// Computes `node` unless it is cached.
+///
+/// Inputs are computed only when the operation reads them, and a select is registered
+/// as a dependent only of the inputs read, so it depends only on the chosen branch.
+fn ensure_cached<T: Clone>(nodes: &mut [Node<T>], node: usize, observers: &mut Observers) {
+    if nodes[node].cache.is_some() {
+        if let Some(profiler) = &mut observers.profiler {
+            profiler.cache_hit(node, &nodes[node].kind);
+        }
+        observers.observe(TraceEventKind::CacheHit, node, &mut nodes[node]);
+        return;
+    }
+
+    if let OpKind::Input(name) = &nodes[node].kind {
+        panic!("input {} is not set", name);
+    }
+
+    let started = observers.profiler.as_mut().map(Profiler::enter);
+    if let OpKind::Port(index) = nodes[node].kind {
+        let NodeId(source) = nodes[node].node_inputs[0];
+        ensure_cached(nodes, source, observers);
+        let NodeOp::Multi { outputs, .. } = &nodes[source].op else {
+            unreachable!("port should read a node with several outputs")
+        };
+        let result = outputs[index].clone();
+        finish_computed(nodes, node, observers, started, result);
+        return;
+    }
+
+    // taken out of the node, so its inputs can be computed while the operation is borrowed
+    let mut taken = Taken {
+        node_inputs: std::mem::take(&mut nodes[node].node_inputs),
+        op: std::mem::replace(&mut nodes[node].op, NodeOp::None),
+        nodes: &mut *nodes,
+        node,
+    };
+    let mut inputs = LazyInputs {
+        nodes: &mut *taken.nodes,
+        ids: &taken.node_inputs,
+        position: 0,
+        read: smallvec![false; taken.node_inputs.len()],
+        observers: &mut *observers,
+    };
+    let result = match &mut taken.op {
+        NodeOp::None => unreachable!("node should not depend on itself"),
+        NodeOp::Single(op) => op(&mut inputs),
+        NodeOp::Multi { op, count, outputs } => {
+            *outputs = op(&mut inputs);
+            assert_eq!(outputs.len(), *count, "wrong number of outputs");
+            // the node itself holds the first output
+            outputs[0].clone()
+        }
+    };
+    let read = inputs.read;
+
+    // only a select skips inputs, other nodes keep the edges they were created with
+    if taken.nodes[node].kind == OpKind::Builtin(BuiltinOp::Select) {
+        // the same input can be passed several times
+        let read_ids = taken
+            .node_inputs
+            .iter()
+            .zip(&read)
+            .filter(|(_, &read)| read)
+            .map(|(input, _)| input.0)
+            .collect::<SmallVec<[usize; 3]>>();
+        for &NodeId(input) in taken.node_inputs.iter() {
+            let dependents = &mut taken.nodes[input].dependents;
+            if !read_ids.contains(&input) {
+                dependents.retain(|dependent| *dependent != node);
+            } else if !dependents.contains(&node) {
+                dependents.push(node);
+            }
+        }
+    }
+
+    drop(taken);
+    finish_computed(nodes, node, observers, started, result);
+}
+
+/// Inputs and operation taken out of a node while it is computed,
+/// put back when dropped, also if the operation panics
+struct Taken<'a, T> {
+    nodes: &'a mut [Node<T>],
+    node: usize,
+    node_inputs: SmallVec<[NodeId; 2]>,
+    op: NodeOp<T>,
+}
+
+thread_local! {
+    // innermost node whose operation panicked, set while the panic unwinds
+    static PANICKED_NODE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+impl<T> Drop for Taken<'_, T> {
+    fn drop(&mut self) {
+        if std::thread::panicking() && PANICKED_NODE.get().is_none() {
+            PANICKED_NODE.set(Some(self.node));
+        }
+        let current = &mut self.nodes[self.node];
+        current.node_inputs = std::mem::take(&mut self.node_inputs);
+        current.op = std::mem::replace(&mut self.op, NodeOp::None);
+    }
+}
+
+/// Caches `result` of `node` and notifies observers
+fn finish_computed<T>(
+    nodes: &mut [Node<T>],
+    node: usize,
+    observers: &mut Observers,
+    started: Option<std::time::Instant>,
+    result: T,
+) {
+    let current = &mut nodes[node];
+    current.cache = Some(result);
+    if let (Some(profiler), Some(started)) = (&mut observers.profiler, started) {
+        profiler.computed(node, &current.kind, started);
+    }
+    observers.observe(TraceEventKind::NodeComputed, node, current);
+}
+
+/// Inputs of a node computed when they are taken by its operation
+struct LazyInputs<'a, T> {
+    nodes: &'a mut [Node<T>],
+    ids: &'a [NodeId],
+    position: usize,
+    read: SmallVec<[bool; 4]>,
+    observers: &'a mut Observers,
+}
+
+impl<T: Clone> Iterator for LazyInputs<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let NodeId(input) = *self.ids.get(self.position)?;
+        self.read[self.position] = true;
+        self.position += 1;
+        ensure_cached(self.nodes, input, self.observers);
+        self.nodes[input].cache.clone()
+    }
+
+    // skipped inputs are not computed
+    fn nth(&mut self, n: usize) -> Option<T> {
+        self.position = self.position.saturating_add(n);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.ids.len().saturating_sub(self.position);
+        (remaining, Some(remaining))
+    }
+}
+
+impl Observers {
+    fn observe<T>(&mut self, kind: TraceEventKind, id: usize, node: &mut Node<T>) {
+        // reading or replacing a value given from outside is not caching
+        if !matches!(node.kind, OpKind::Input(_) | OpKind::Constant(_)) {
+            node.stats.record(kind);
+        }
+        if let Some(tracer) = &mut self.tracer {
+            tracer(&TraceEvent {
+                kind,
+                node: id,
+                name: node.kind.name(),
+                label: node.label.as_deref(),
+            });
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::comp_graph3::CompGraph;
+    use crate::comp_graph3::{CompGraph, OpKind, OpRegistry};
+    use crate::ops::BuiltinOp;
 
     #[test]
     fn test_simple() {
@@ -156,4 +647,271 @@ mod test {
         assert_eq!(graph.cache(node2), Some(4.0f32));
         assert_eq!(graph.cache(node3), None);
     }
+
+    #[test]
+    fn test_builtin_ops() {
+        let mut graph = CompGraph::new();
+        let x1 = graph.add_input_node("x1");
+        let x2 = graph.add_input_node("x2");
+        graph.set_input("x1", 2.0f32);
+        graph.set_input("x2", 3.0f32);
+
+        let node = graph.add_op(BuiltinOp::Pow, [x1, x2]);
+        let node = graph.add_op(BuiltinOp::Neg, [node]);
+
+        assert_eq!(graph.compute(node), -8.0);
+        assert_eq!(graph.op_kind(node), &OpKind::Builtin(BuiltinOp::Neg));
+        assert_eq!(graph.op_kind(x1), &OpKind::Input("x1".into()));
+    }
+
+    #[test]
+    fn test_tracer() {
+        use crate::trace::TraceEventKind::*;
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut graph = CompGraph::new();
+        let x1 = graph.add_input_node("x1");
+        let node = graph.add_node([x1], |x| x.next().unwrap() * 2.0);
+        let result = graph.add_op(BuiltinOp::Neg, [node]);
+        graph.set_input("x1", 1.0f32);
+
+        let events = Rc::new(RefCell::new(vec![]));
+        let recorded = events.clone();
+        graph.set_tracer(move |event| {
+            recorded
+                .borrow_mut()
+                .push((event.kind, event.node, event.name.map(str::to_owned)))
+        });
+        graph.compute(result);
+        graph.compute(result);
+        graph.set_input("x1", 2.0);
+        graph.clear_tracer();
+        graph.compute(result);
+
+        let name = |name: &str| Some(name.to_owned());
+        assert_eq!(
+            *events.borrow(),
+            [
+                (CacheHit, x1.0, name("x1")),
+                (NodeComputed, node.0, None),
+                (NodeComputed, result.0, name("neg")),
+                (CacheHit, result.0, name("neg")),
+                (CacheInvalidated, x1.0, name("x1")),
+                (CacheInvalidated, node.0, None),
+                (CacheInvalidated, result.0, name("neg")),
+                (InputSet, x1.0, name("x1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let result = graph.add_op(BuiltinOp::Neg, [x]);
+        graph.set_label(result, "negated");
+        graph.set_input("x", 1.0f32);
+        assert_eq!(graph.label(result), Some("negated"));
+        assert_eq!(graph.label(x), None);
+
+        let labels = Rc::new(RefCell::new(vec![]));
+        let recorded = labels.clone();
+        graph.set_tracer(move |event| recorded.borrow_mut().push(event.label.map(str::to_owned)));
+        graph.compute(result);
+        assert_eq!(*labels.borrow(), [None, Some("negated".to_owned())]);
+        assert_eq!(
+            format!("{:?}", graph),
+            "[NodeRef { id: 0, kind: Input(\"x\"), label: None, inputs: [], cached: Some(1.0) }, \
+            NodeRef { id: 1, kind: Builtin(Neg), label: Some(\"negated\"), inputs: [NodeId(0)], cached: Some(-1.0) }]"
+        );
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "node \"discounted_cashflow\" failed: loop of node 1 did not converge"
+    )]
+    fn test_labeled_failure() {
+        use crate::comp_graph3::{LoopOptions, SubGraph};
+
+        let mut graph = CompGraph::new();
+        let start = graph.add_input_node("start");
+        let body = SubGraph::new(["x"], ["next"], |graph, params| {
+            vec![graph.add_op(BuiltinOp::Neg, params.iter().copied())]
+        });
+        let state = graph.add_loop_node(&body, [start], [], LoopOptions::default())[0];
+        let result = graph.add_op(BuiltinOp::Mul, [state, start]);
+        graph.set_label(result, "result");
+        // the loop itself is reported, not the requested node
+        let node = graph.node(state).inputs()[0];
+        graph.set_label(node, "discounted_cashflow");
+        graph.set_input("start", 1.0f64);
+        graph.compute(result);
+    }
+
+    #[test]
+    fn test_lazy_select() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let y = graph.add_input_node("y");
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let expensive = graph.add_node([y], move |args| {
+            counter.set(counter.get() + 1);
+            args.next().unwrap() * 100.0
+        });
+        let zero = graph.add_op(BuiltinOp::Sub, [x, x]);
+        let condition = graph.add_op(BuiltinOp::Gt, [x, zero]);
+        let result = graph.add_op(BuiltinOp::Select, [condition, x, expensive]);
+        graph.set_input("x", 1.0f32);
+        graph.set_input("y", 2.0f32);
+
+        assert_eq!(graph.compute(result), 1.0);
+        assert_eq!(calls.get(), 0);
+        // the other branch is not a dependency
+        graph.set_input("y", 3.0);
+        assert_eq!(graph.cache(result), Some(1.0));
+
+        graph.set_input("x", -1.0);
+        assert_eq!(graph.compute(result), 300.0);
+        assert_eq!(calls.get(), 1);
+        graph.set_input("y", 4.0);
+        assert_eq!(graph.cache(result), None);
+        assert_eq!(graph.compute(result), 400.0);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_static_dependents() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let y = graph.add_input_node("y");
+        let first = graph.add_node([x, y], |args| args.next().unwrap());
+        let one = graph.add_constant(1.0);
+        let select = graph.add_op(BuiltinOp::Select, [one, x, y]);
+        graph.set_input("x", 1.0f32);
+        graph.set_input("y", 2.0);
+        graph.compute(first);
+        graph.compute(select);
+
+        // only a select drops edges of inputs it didn't read
+        assert_eq!(graph.node(y).dependents().collect::<Vec<_>>(), [first]);
+        graph
+            .replace_op(select, |args| args.nth(2).unwrap())
+            .unwrap();
+        assert_eq!(graph.compute(select), 2.0);
+        graph.set_input("y", 3.0);
+        assert_eq!(graph.compute(select), 3.0);
+        assert_eq!(graph.compute(first), 1.0);
+    }
+
+    #[test]
+    fn test_panicking_op() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let checked = graph.add_node([x], |args| {
+            let x = args.next().unwrap();
+            assert!(x >= 0.0, "negative input");
+            x
+        });
+        graph.set_label(checked, "checked");
+        let result = graph.add_op(BuiltinOp::Sqrt, [checked]);
+        graph.set_input("x", -4.0f64);
+        let payload = catch_unwind(AssertUnwindSafe(|| graph.compute(result))).unwrap_err();
+        assert_eq!(
+            payload.downcast_ref::<String>().map(String::as_str),
+            Some("node \"checked\" failed: negative input")
+        );
+
+        // the failed node keeps its inputs and operation
+        assert_eq!(graph.node(checked).inputs(), [x]);
+        graph.set_input("x", 4.0);
+        assert_eq!(graph.compute(result), 2.0);
+    }
+
+    #[test]
+    fn test_cache_stats() {
+        let mut graph = CompGraph::new();
+        let x1 = graph.add_input_node("x1");
+        let x2 = graph.add_input_node("x2");
+        let left = graph.add_op(BuiltinOp::Neg, [x1]);
+        let right = graph.add_op(BuiltinOp::Neg, [x2]);
+        let result = graph.add_op(BuiltinOp::Add, [left, right]);
+        graph.set_input("x1", 1.0f32);
+        graph.set_input("x2", 2.0f32);
+
+        for i in 0..10 {
+            graph.set_input("x1", i as f32);
+            graph.compute(result);
+        }
+        // only the changed branch is recomputed
+        let stats = graph.node_cache_stats(right);
+        assert_eq!((stats.hits, stats.misses, stats.invalidations), (9, 1, 0));
+        let stats = graph.node_cache_stats(left);
+        assert_eq!((stats.hits, stats.misses, stats.invalidations), (0, 10, 9));
+        let stats = graph.cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.invalidations), (9, 21, 18));
+        // inputs keep no counters
+        assert_eq!(graph.node_cache_stats(x1), Default::default());
+        assert_eq!(stats.hit_rate(), 9.0 / 30.0);
+
+        graph.reset_cache_stats();
+        assert_eq!(graph.cache_stats(), Default::default());
+    }
+
+    #[test]
+    fn test_constants() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let two = graph.add_constant(2.0);
+        let offset = graph.add_parameter("offset", 1.0);
+        let pow = graph.add_op(BuiltinOp::Pow, [x, two]);
+        let result = graph.add_op(BuiltinOp::Add, [pow, offset]);
+        graph.set_input("x", 3.0f64);
+        assert_eq!(
+            graph.op_kind(offset),
+            &OpKind::Constant(Some("offset".into()))
+        );
+        assert_eq!(graph.op_kind(offset).name(), Some("offset"));
+        assert_eq!(graph.compute(result), 10.0);
+
+        graph.set_constant(offset, -1.0);
+        assert_eq!(graph.cache(pow), Some(9.0));
+        assert_eq!(graph.compute(result), 8.0);
+        graph.invalidate_node(two);
+        assert_eq!(graph.compute(result), 8.0);
+
+        let program = graph.compile(result).unwrap();
+        assert_eq!(program.input_names().collect::<Vec<_>>(), ["x"]);
+        assert_eq!(program.run(&[2.0]), 3.0);
+
+        let registry = OpRegistry::with_builtins();
+        let json = graph.to_json(false).unwrap();
+        let binary = graph.to_binary().unwrap();
+        for mut restored in [
+            CompGraph::from_json(&json, &registry).unwrap(),
+            CompGraph::from_binary(&binary, &registry).unwrap(),
+        ] {
+            assert_eq!(restored.op_kind(two), &OpKind::Constant(None));
+            assert_eq!(restored.compute(result), 8.0);
+            restored.set_constant(two, 3.0);
+            assert_eq!(restored.compute(result), 26.0);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "wrong number of inputs for sin")]
+    fn test_builtin_arity() {
+        let mut graph = CompGraph::<f32>::new();
+        let x1 = graph.add_input_node("x1");
+        graph.add_op(BuiltinOp::Sin, [x1, x1]);
+    }
 }
@@ -0,0 +1,474 @@
+//! Compact binary encoding of [`CompGraph`].
+//!
+//! All numbers are little-endian, layout is:
+//! - magic `CGRF` and `u16` format version
+//! - string table: `u32` count, then `u32` length and utf-8 bytes for each string
+//! - node table: `u32` count, then `u8` op code and `u32` operand for each node,
+//!   operand is a string index for inputs, parameters and named operations,
+//!   `u32::MAX` for unnamed constants, index in [`BuiltinOp::ALL`] for builtin operations
+//!   and output index for ports
+//! - `node_inputs` adjacency: `u32` offset for each node plus the total,
+//!   then `u32` node ids of all inputs
+//! - values: `u32` count, then `u32` node id and `f64` value for each set input and constant
+//! - `u32` FNV-1a checksum of everything above
+
+use super::{builtin_op, CompGraph, NodeId, NodeOp, OpKind, OpRegistry};
+use crate::ops::{BuiltinOp, Scalar};
+use smallvec::SmallVec;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+const MAGIC: &[u8; 4] = b"CGRF";
+// version 2 added constants and version 3 ports, files of older versions are still readable
+const FORMAT_VERSION: u16 = 3;
+
+const OP_INPUT: u8 = 0;
+const OP_BUILTIN: u8 = 1;
+const OP_NAMED: u8 = 2;
+const OP_CONSTANT: u8 = 3;
+const OP_PORT: u8 = 4;
+
+const NO_NAME: u32 = u32::MAX;
+
+/// Error of saving or loading graph in binary format
+#[derive(Debug)]
+pub enum BinaryError {
+    /// Data does not start with the expected magic bytes
+    BadMagic,
+    UnsupportedVersion {
+        found: u16,
+        supported: u16,
+    },
+    /// Data ended before all declared content was read
+    Truncated,
+    ChecksumMismatch,
+    /// Data is structurally invalid
+    Corrupted(&'static str),
+    /// Node was added with [`CompGraph::add_node`] so its operation can't be restored
+    AnonymousOp(NodeId),
+    /// Operation is not in the [`OpRegistry`]
+    UnknownOp(String),
+    /// Node refers to an input that doesn't exist or depends on the node
+    InvalidInput {
+        node: usize,
+        input: usize,
+    },
+    WrongArity {
+        node: usize,
+        op: &'static str,
+    },
+    /// Several inputs have the same name, so only one of them could be set
+    DuplicateInput(String),
+}
+
+impl Display for BinaryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BinaryError::BadMagic => f.write_str("not a graph file"),
+            BinaryError::UnsupportedVersion { found, supported } => write!(
+                f,
+                "unsupported format version {}, expected {}",
+                found, supported
+            ),
+            BinaryError::Truncated => f.write_str("unexpected end of data"),
+            BinaryError::ChecksumMismatch => f.write_str("checksum mismatch, data is corrupted"),
+            BinaryError::Corrupted(reason) => write!(f, "corrupted data: {}", reason),
+            BinaryError::AnonymousOp(node) => {
+                write!(f, "operation of node {} has no name", node.0)
+            }
+            BinaryError::UnknownOp(op) => write!(f, "unknown operation {}", op),
+            BinaryError::InvalidInput { node, input } => {
+                write!(f, "node {} refers to invalid input {}", node, input)
+            }
+            BinaryError::WrongArity { node, op } => {
+                write!(f, "wrong number of inputs for {} in node {}", op, node)
+            }
+            BinaryError::DuplicateInput(name) => write!(f, "several inputs are named {}", name),
+        }
+    }
+}
+
+impl std::error::Error for BinaryError {}
+
+impl<T: Scalar> CompGraph<T> {
+    /// Encodes structure of the graph and values of its inputs in compact binary format
+    pub fn to_binary(&self) -> Result<Vec<u8>, BinaryError> {
+        let mut strings = StringTable::default();
+        let mut node_table = Vec::with_capacity(self.nodes.len());
+        for (id, node) in self.nodes.iter().enumerate() {
+            node_table.push(match &node.kind {
+                OpKind::Input(name) => (OP_INPUT, strings.index(name)),
+                OpKind::Constant(name) => (
+                    OP_CONSTANT,
+                    name.as_ref().map_or(NO_NAME, |name| strings.index(name)),
+                ),
+                OpKind::Builtin(op) => (OP_BUILTIN, builtin_code(*op)),
+                OpKind::Named(name) => (OP_NAMED, strings.index(name)),
+                OpKind::Custom => return Err(BinaryError::AnonymousOp(NodeId(id))),
+                &OpKind::Port(index) => (
+                    OP_PORT,
+                    u32::try_from(index).expect("graph is too large for binary format"),
+                ),
+            });
+        }
+
+        let mut out = Writer(Vec::with_capacity(16 + self.nodes.len() * 16));
+        out.0.extend_from_slice(MAGIC);
+        out.u16(FORMAT_VERSION);
+
+        out.len(strings.strings.len());
+        for string in &strings.strings {
+            out.len(string.len());
+            out.0.extend_from_slice(string.as_bytes());
+        }
+
+        out.len(node_table.len());
+        for (code, operand) in node_table {
+            out.0.push(code);
+            out.u32(operand);
+        }
+
+        let mut offset = 0;
+        for node in &self.nodes {
+            out.len(offset);
+            offset += node.node_inputs.len();
+        }
+        out.len(offset);
+        for node in &self.nodes {
+            for input in &node.node_inputs {
+                out.len(input.0);
+            }
+        }
+
+        let values = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| matches!(node.kind, OpKind::Input(_) | OpKind::Constant(_)))
+            .filter_map(|(id, node)| Some((id, node.cache?)))
+            .collect::<Vec<_>>();
+        out.len(values.len());
+        for (id, value) in values {
+            out.len(id);
+            out.0.extend_from_slice(&value.to_f64().to_le_bytes());
+        }
+
+        let checksum = fnv1a(&out.0);
+        out.u32(checksum);
+        Ok(out.0)
+    }
+
+    /// Restores graph saved by [`to_binary`](Self::to_binary),
+    /// named operations are looked up in `registry`
+    pub fn from_binary(data: &[u8], registry: &OpRegistry<T>) -> Result<Self, BinaryError> {
+        if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+            return Err(BinaryError::BadMagic);
+        }
+        let mut reader = Reader {
+            data,
+            pos: MAGIC.len(),
+        };
+        let version = reader.u16()?;
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(BinaryError::UnsupportedVersion {
+                found: version,
+                supported: FORMAT_VERSION,
+            });
+        }
+        let (payload, checksum) = data.split_last_chunk::<4>().ok_or(BinaryError::Truncated)?;
+        if fnv1a(payload) != u32::from_le_bytes(*checksum) {
+            return Err(BinaryError::ChecksumMismatch);
+        }
+        reader.data = payload;
+
+        let string_count = reader.len()?;
+        let mut strings = Vec::with_capacity(string_count.min(payload.len()));
+        for _ in 0..string_count {
+            let len = reader.len()?;
+            let bytes = reader.bytes(len)?;
+            let string = std::str::from_utf8(bytes)
+                .map_err(|_| BinaryError::Corrupted("string is not valid utf-8"))?;
+            strings.push(string.to_owned());
+        }
+        let string = |index: u32| {
+            strings
+                .get(index as usize)
+                .ok_or(BinaryError::Corrupted("string index out of bounds"))
+        };
+
+        let node_count = reader.len()?;
+        let mut node_table = Vec::with_capacity(node_count.min(payload.len()));
+        for _ in 0..node_count {
+            node_table.push((reader.u8()?, reader.u32()?));
+        }
+
+        let mut offsets = Vec::with_capacity(node_table.len() + 1);
+        for _ in 0..=node_count {
+            offsets.push(reader.len()?);
+        }
+        let edge_count = *offsets.last().unwrap();
+        if offsets.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(BinaryError::Corrupted("adjacency offsets are not sorted"));
+        }
+        let mut edges = Vec::with_capacity(edge_count.min(payload.len()));
+        for _ in 0..edge_count {
+            edges.push(reader.len()?);
+        }
+
+        let mut graph = CompGraph::new();
+        graph.nodes.reserve(node_count);
+        // inputs can be defined after the node, so they are attached once all nodes exist
+        let mut wiring = Vec::with_capacity(node_count);
+        for (id, (code, operand)) in node_table.into_iter().enumerate() {
+            let node_inputs = edges[offsets[id]..offsets[id + 1]]
+                .iter()
+                .map(|&input| NodeId(input))
+                .collect::<SmallVec<_>>();
+            match code {
+                OP_INPUT => {
+                    if !node_inputs.is_empty() {
+                        return Err(BinaryError::Corrupted("input node has inputs"));
+                    }
+                    let name = string(operand)?;
+                    if graph.graph_inputs.contains_key(name.as_str()) {
+                        return Err(BinaryError::DuplicateInput(name.clone()));
+                    }
+                    graph.add_input_node(name.clone());
+                }
+                OP_CONSTANT => {
+                    if !node_inputs.is_empty() {
+                        return Err(BinaryError::Corrupted("constant node has inputs"));
+                    }
+                    let name = match operand {
+                        NO_NAME => None,
+                        index => Some(string(index)?.clone().into()),
+                    };
+                    // value is set from the values table
+                    graph.push_node(SmallVec::new(), OpKind::Constant(name), NodeOp::None);
+                }
+                OP_BUILTIN => {
+                    let op = *BuiltinOp::ALL
+                        .get(operand as usize)
+                        .ok_or(BinaryError::Corrupted("unknown builtin operation"))?;
+                    if op.arity() != node_inputs.len() {
+                        return Err(BinaryError::WrongArity {
+                            node: id,
+                            op: op.name(),
+                        });
+                    }
+                    let node = graph.push_node(
+                        SmallVec::new(),
+                        OpKind::Builtin(op),
+                        NodeOp::Single(builtin_op(op)),
+                    );
+                    wiring.push((node, node_inputs));
+                }
+                OP_NAMED => {
+                    let name = string(operand)?;
+                    let (kind, op) = registry
+                        .instantiate(name)
+                        .ok_or_else(|| BinaryError::UnknownOp(name.clone()))?;
+                    let node = graph.push_node(SmallVec::new(), kind, op);
+                    wiring.push((node, node_inputs));
+                }
+                OP_PORT => {
+                    if node_inputs.len() != 1 {
+                        return Err(BinaryError::Corrupted("port should have one input"));
+                    }
+                    let node = graph.push_node(
+                        SmallVec::new(),
+                        OpKind::Port(operand as usize),
+                        NodeOp::None,
+                    );
+                    wiring.push((node, node_inputs));
+                }
+                _ => return Err(BinaryError::Corrupted("unknown op code")),
+            }
+        }
+        graph
+            .attach_inputs(wiring)
+            .map_err(|(node, input)| BinaryError::InvalidInput {
+                node: node.0,
+                input: input.0,
+            })?;
+        if let Some((node, input)) = graph.invalid_port() {
+            return Err(BinaryError::InvalidInput {
+                node: node.0,
+                input: input.0,
+            });
+        }
+
+        let value_count = reader.len()?;
+        for _ in 0..value_count {
+            let id = reader.len()?;
+            let value = f64::from_le_bytes(reader.bytes(8)?.try_into().unwrap());
+            match graph.nodes.get_mut(id) {
+                Some(node) if matches!(node.kind, OpKind::Input(_) | OpKind::Constant(_)) => {
+                    node.cache = Some(T::from_f64(value))
+                }
+                _ => return Err(BinaryError::Corrupted("value for non-input node")),
+            }
+        }
+        if graph
+            .nodes
+            .iter()
+            .any(|node| matches!(node.kind, OpKind::Constant(_)) && node.cache.is_none())
+        {
+            return Err(BinaryError::Corrupted("constant without value"));
+        }
+
+        if reader.pos != payload.len() {
+            return Err(BinaryError::Corrupted("trailing data"));
+        }
+        Ok(graph)
+    }
+}
+
+fn builtin_code(op: BuiltinOp) -> u32 {
+    BuiltinOp::ALL.iter().position(|&x| x == op).unwrap() as u32
+}
+
+fn fnv1a(data: &[u8]) -> u32 {
+    data.iter().fold(0x811c9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+#[derive(Default)]
+struct StringTable<'a> {
+    strings: Vec<&'a str>,
+    indices: HashMap<&'a str, u32>,
+}
+
+impl<'a> StringTable<'a> {
+    fn index(&mut self, string: &'a str) -> u32 {
+        let strings = &mut self.strings;
+        *self.indices.entry(string).or_insert_with(|| {
+            strings.push(string);
+            strings.len() as u32 - 1
+        })
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, value: usize) {
+        self.u32(u32::try_from(value).expect("graph is too large for binary format"));
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], BinaryError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or(BinaryError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, BinaryError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, BinaryError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, BinaryError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize, BinaryError> {
+        self.u32().map(|x| x as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_graph() -> (CompGraph<f32>, NodeId) {
+        let mut graph = CompGraph::new();
+        let x1 = graph.add_input_node("x1");
+        let x2 = graph.add_input_node("x2");
+        let sum = graph.add_op(BuiltinOp::Add, [x1, x2]);
+        let result = graph.add_named_node("square", [sum], square);
+        graph.set_input("x1", 1.0);
+        graph.set_input("x2", 2.0);
+        (graph, result)
+    }
+
+    fn square(args: &mut dyn Iterator<Item = f32>) -> f32 {
+        args.next().unwrap().powi(2)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let (graph, result) = sample_graph();
+        let mut registry = OpRegistry::new();
+        registry.register("square", square);
+
+        let data = graph.to_binary().unwrap();
+        let mut restored = CompGraph::from_binary(&data, &registry).unwrap();
+        assert_eq!(restored.compute(result), 9.0);
+        restored.set_input("x1", 2.0);
+        assert_eq!(restored.compute(result), 16.0);
+    }
+
+    #[test]
+    fn test_errors() {
+        let (graph, _) = sample_graph();
+        let registry = OpRegistry::<f32>::new();
+        let data = graph.to_binary().unwrap();
+
+        assert!(matches!(
+            CompGraph::from_binary(&data[1..], &registry),
+            Err(BinaryError::BadMagic)
+        ));
+
+        let mut newer = data.clone();
+        newer[4] = 4;
+        assert!(matches!(
+            CompGraph::from_binary(&newer, &registry),
+            Err(BinaryError::UnsupportedVersion {
+                found: 4,
+                supported: 3
+            })
+        ));
+
+        let mut corrupted = data.clone();
+        corrupted[10] ^= 1;
+        assert!(matches!(
+            CompGraph::from_binary(&corrupted, &registry),
+            Err(BinaryError::ChecksumMismatch)
+        ));
+
+        assert!(matches!(
+            CompGraph::from_binary(&data, &registry),
+            Err(BinaryError::UnknownOp(op)) if op == "square"
+        ));
+
+        let mut graph = CompGraph::<f32>::new();
+        graph.add_input_node("x");
+        graph.add_input_node("x");
+        assert!(matches!(
+            CompGraph::from_binary(&graph.to_binary().unwrap(), &registry),
+            Err(BinaryError::DuplicateInput(name)) if name == "x"
+        ));
+    }
+}
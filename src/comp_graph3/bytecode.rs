@@ -0,0 +1,257 @@
+use super::{CompGraph, NodeId, OpKind};
+use crate::ops::{BuiltinOp, Scalar};
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+
+/// Node of [`CompGraph`] lowered to a linear list of register-based instructions.
+///
+/// Has no caching, but avoids the overhead of dynamic dispatch and recursion per node,
+/// so it is much faster for graphs of cheap scalar operations that are fully recomputed.
+pub struct Program<T> {
+    instructions: Vec<Instruction>,
+    // registers of inputs are the first ones in the same order
+    inputs: Vec<Cow<'static, str>>,
+    // values of constants at the time of compilation and their registers
+    constants: Vec<(u32, T)>,
+    // names of parameters and their positions in `constants`
+    parameters: Vec<(Cow<'static, str>, usize)>,
+    register_count: usize,
+    output: u32,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Instruction {
+    op: BuiltinOp,
+    dst: u32,
+    args: [u32; 3],
+}
+
+/// Error of compiling graph into a [`Program`]
+#[derive(Debug)]
+pub enum CompileError {
+    /// Node is not a builtin operation or an input
+    UnsupportedOp(NodeId),
+    /// Name can't be used as an identifier in generated code
+    InvalidIdentifier(String),
+    /// Several inputs have the same name, so they can't all be parameters of generated code
+    DuplicateInput(String),
+}
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompileError::UnsupportedOp(node) => {
+                write!(f, "node {} is not a builtin operation", node.0)
+            }
+            CompileError::InvalidIdentifier(name) => {
+                write!(f, "{} is not a valid identifier", name)
+            }
+            CompileError::DuplicateInput(name) => {
+                write!(f, "several inputs are named {}", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+impl<T: Scalar> CompGraph<T> {
+    /// Compiles computation of `output` into a [`Program`].
+    ///
+    /// Inputs of the program are the graph inputs `output` depends on, in order of their creation.
+    /// Constants are copied into the program, so later changes of them don't affect it.
+    pub fn compile(&self, output: NodeId) -> Result<Program<T>, CompileError> {
+        let order = self.postorder(output);
+
+        const NO_REGISTER: u32 = u32::MAX;
+        let mut registers = vec![NO_REGISTER; self.nodes.len()];
+        let mut inputs = order
+            .iter()
+            .filter_map(|&id| match &self.nodes[id].kind {
+                OpKind::Input(name) => Some((id, name.clone())),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        inputs.sort_by_key(|(id, _)| *id);
+        for (register, (id, _)) in inputs.iter().enumerate() {
+            registers[*id] = register as u32;
+        }
+
+        let mut register_count = inputs.len() as u32;
+        let mut constants = vec![];
+        let mut parameters = vec![];
+        let mut instructions = Vec::with_capacity(order.len() - inputs.len());
+        for id in order {
+            let node = &self.nodes[id];
+            let op = match &node.kind {
+                OpKind::Input(_) => continue,
+                OpKind::Constant(name) => {
+                    let value = node.cache.expect("constant should always be cached");
+                    if let Some(name) = name {
+                        parameters.push((name.clone(), constants.len()));
+                    }
+                    registers[id] = register_count;
+                    constants.push((register_count, value));
+                    register_count += 1;
+                    continue;
+                }
+                OpKind::Builtin(op) => *op,
+                _ => return Err(CompileError::UnsupportedOp(NodeId(id))),
+            };
+            let mut args = [0; 3];
+            for (arg, input) in args.iter_mut().zip(&node.node_inputs) {
+                *arg = registers[input.0];
+            }
+            registers[id] = register_count;
+            instructions.push(Instruction {
+                op,
+                dst: register_count,
+                args,
+            });
+            register_count += 1;
+        }
+
+        Ok(Program {
+            instructions,
+            inputs: inputs.into_iter().map(|(_, name)| name).collect(),
+            constants,
+            parameters,
+            register_count: register_count as usize,
+            output: registers[output.0],
+        })
+    }
+}
+
+impl<T: Scalar> Program<T> {
+    /// Names of inputs in the order [`run`](Self::run) expects their values
+    pub fn input_names(&self) -> impl Iterator<Item = &str> {
+        self.inputs.iter().map(|name| &**name)
+    }
+
+    /// Position of input `name` in [`input_names`](Self::input_names)
+    pub fn input_index(&self, name: &str) -> Option<usize> {
+        self.inputs.iter().position(|input| input == name)
+    }
+
+    /// Changes value of parameter `name` copied into the program at compilation
+    pub fn set_parameter(&mut self, name: &str, value: T) {
+        let mut found = false;
+        for (_, index) in self.parameters.iter().filter(|(param, _)| param == name) {
+            self.constants[*index].1 = value;
+            found = true;
+        }
+        assert!(found, "no such parameter");
+    }
+
+    /// Computes output for values of `inputs`
+    pub fn run(&self, inputs: &[T]) -> T {
+        self.run_with(&mut Vec::new(), inputs)
+    }
+
+    /// Same as [`run`](Self::run) but reuses `registers` buffer between calls
+    pub fn run_with(&self, registers: &mut Vec<T>, inputs: &[T]) -> T {
+        assert_eq!(inputs.len(), self.inputs.len(), "wrong number of inputs");
+        registers.clear();
+        registers.extend_from_slice(inputs);
+        registers.resize(self.register_count, T::ZERO);
+        for &(register, value) in &self.constants {
+            registers[register as usize] = value;
+        }
+
+        for &Instruction { op, dst, args } in &self.instructions {
+            let [a, b, c] = args.map(|x| registers[x as usize]);
+            registers[dst as usize] = match op {
+                BuiltinOp::Add => a + b,
+                BuiltinOp::Sub => a - b,
+                BuiltinOp::Mul => a * b,
+                BuiltinOp::Div => a / b,
+                BuiltinOp::Neg => -a,
+                BuiltinOp::Sin => a.sin(),
+                BuiltinOp::Cos => a.cos(),
+                BuiltinOp::Exp => a.exp(),
+                BuiltinOp::Pow => a.powf(b),
+                _ => op.apply(&[a, b, c][..op.arity()]),
+            };
+        }
+        registers[self.output as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_as_compute() {
+        let mut graph = CompGraph::new();
+        let x1 = graph.add_input_node("x1");
+        let x2 = graph.add_input_node("x2");
+        let x3 = graph.add_input_node("x3");
+        let x4 = graph.add_input_node("x4");
+        let exponent = graph.add_op(BuiltinOp::Abs, [x4]);
+        let pow = graph.add_op(BuiltinOp::Pow, [x3, exponent]);
+        let sum = graph.add_op(BuiltinOp::Add, [x2, pow]);
+        let sin = graph.add_op(BuiltinOp::Sin, [sum]);
+        let mul = graph.add_op(BuiltinOp::Mul, [x2, sin]);
+        let result = graph.add_op(BuiltinOp::Add, [x1, mul]);
+
+        let program = graph.compile(result).unwrap();
+        assert_eq!(
+            program.input_names().collect::<Vec<_>>(),
+            ["x1", "x2", "x3", "x4"]
+        );
+        assert_eq!(program.input_index("x3"), Some(2));
+
+        let mut registers = vec![];
+        for values in [[1.0f32, 2.0, 3.0, -3.0], [2.0, 3.0, 4.0, 3.0]] {
+            for (name, value) in program.input_names().zip(values) {
+                graph.set_input(name, value);
+            }
+            assert_eq!(program.run(&values), graph.compute(result));
+            assert_eq!(
+                program.run_with(&mut registers, &values),
+                graph.compute(result)
+            );
+        }
+    }
+
+    #[test]
+    fn test_set_parameter() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let scale = graph.add_parameter("scale", 2.0);
+        let offset = graph.add_constant(1.0);
+        let scaled = graph.add_op(BuiltinOp::Mul, [x, scale]);
+        let result = graph.add_op(BuiltinOp::Add, [scaled, offset]);
+
+        let mut program = graph.compile(result).unwrap();
+        assert_eq!(program.input_names().collect::<Vec<_>>(), ["x"]);
+        assert_eq!(program.run(&[3.0f64]), 7.0);
+        program.set_parameter("scale", 3.0);
+        assert_eq!(program.run(&[3.0]), 10.0);
+        // the graph keeps its own value
+        graph.set_input("x", 3.0);
+        assert_eq!(graph.compute(result), 7.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "no such parameter")]
+    fn test_unknown_parameter() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let result = graph.add_op(BuiltinOp::Neg, [x]);
+        graph.compile(result).unwrap().set_parameter("x", 1.0f32);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let mut graph = CompGraph::<f32>::new();
+        let x1 = graph.add_input_node("x1");
+        let custom = graph.add_node([x1], |x| x.next().unwrap());
+        let result = graph.add_op(BuiltinOp::Neg, [custom]);
+        assert!(matches!(
+            graph.compile(result),
+            Err(CompileError::UnsupportedOp(node)) if node.0 == custom.0
+        ));
+    }
+}
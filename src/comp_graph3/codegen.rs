@@ -0,0 +1,259 @@
+use super::{CompGraph, CompileError, NodeId, OpKind};
+use crate::ops::{BuiltinOp, Scalar};
+use std::fmt::Write;
+
+impl<T: Scalar> CompGraph<T> {
+    /// Generates source of a standalone Rust function `fn_name` computing `output`.
+    ///
+    /// Parameters of the function are the graph inputs `output` depends on,
+    /// named after them and ordered by creation, same as for [`compile`](Self::compile).
+    /// Can be called from a build script to write the function into `OUT_DIR`
+    /// and `include!` it, so the graph stays the source of truth.
+    pub fn to_rust_fn(&self, fn_name: &str, output: NodeId) -> Result<String, CompileError> {
+        check_identifier(fn_name)?;
+        let order = self.postorder(output);
+        let mut inputs = order
+            .iter()
+            .filter(|&&id| matches!(self.nodes[id].kind, OpKind::Input(_)))
+            .copied()
+            .collect::<Vec<_>>();
+        inputs.sort_unstable();
+
+        let mut names = vec![String::new(); self.nodes.len()];
+        let mut params = vec![];
+        for &id in &inputs {
+            let OpKind::Input(name) = &self.nodes[id].kind else {
+                unreachable!()
+            };
+            check_identifier(name)?;
+            if names.iter().any(|other| other == name) {
+                return Err(CompileError::DuplicateInput(name.to_string()));
+            }
+            params.push(format!("{}: {}", name, T::TYPE_NAME));
+            names[id] = name.to_string();
+        }
+
+        let mut out = String::new();
+        writeln!(
+            out,
+            "pub fn {}({}) -> {} {{",
+            fn_name,
+            params.join(", "),
+            T::TYPE_NAME
+        )
+        .unwrap();
+        for id in order {
+            let node = &self.nodes[id];
+            let op = match node.kind {
+                OpKind::Input(_) => continue,
+                OpKind::Constant(_) => {
+                    let value = node.cache.expect("constant should always be cached");
+                    names[id] = format!("v{}", id);
+                    writeln!(
+                        out,
+                        "    let {}: {} = {};",
+                        names[id],
+                        T::TYPE_NAME,
+                        rust_literal(value)
+                    )
+                    .unwrap();
+                    continue;
+                }
+                OpKind::Builtin(op) => op,
+                _ => return Err(CompileError::UnsupportedOp(NodeId(id))),
+            };
+            let args = node
+                .node_inputs
+                .iter()
+                .map(|input| &*names[input.0])
+                .collect::<Vec<_>>();
+            let expr = rust_expr(op, &args);
+            names[id] = format!("v{}", id);
+            writeln!(out, "    let {} = {};", names[id], expr).unwrap();
+        }
+        writeln!(out, "    {}", names[output.0]).unwrap();
+        out.push_str("}\n");
+        Ok(out)
+    }
+}
+
+fn rust_literal<T: Scalar>(value: T) -> String {
+    let float = value.to_f64();
+    if float.is_nan() {
+        format!("{}::NAN", T::TYPE_NAME)
+    } else if float.is_infinite() {
+        let sign = if float > 0.0 { "" } else { "NEG_" };
+        format!("{}::{}INFINITY", T::TYPE_NAME, sign)
+    } else {
+        // debug formatting always has a decimal point or exponent and round-trips
+        format!("{:?}", value)
+    }
+}
+
+fn rust_expr(op: BuiltinOp, args: &[&str]) -> String {
+    let compare = |operator: &str| {
+        format!(
+            "if {} {} {} {{ 1.0 }} else {{ 0.0 }}",
+            args[0], operator, args[1]
+        )
+    };
+    match op {
+        BuiltinOp::Add => format!("{} + {}", args[0], args[1]),
+        BuiltinOp::Sub => format!("{} - {}", args[0], args[1]),
+        BuiltinOp::Mul => format!("{} * {}", args[0], args[1]),
+        BuiltinOp::Div => format!("{} / {}", args[0], args[1]),
+        BuiltinOp::Neg => format!("-{}", args[0]),
+        BuiltinOp::Pow => format!("{}.powf({})", args[0], args[1]),
+        // same comparisons as `BuiltinOp::apply`, unlike `f32::min` and `f32::max` for NaN
+        BuiltinOp::Min => min(args[0], args[1]),
+        BuiltinOp::Max => max(args[0], args[1]),
+        BuiltinOp::Clamp => {
+            let max = max(args[0], args[1]);
+            format!("if {1} < ({0}) {{ {1} }} else {{ {0} }}", max, args[2])
+        }
+        BuiltinOp::Lt => compare("<"),
+        BuiltinOp::Le => compare("<="),
+        BuiltinOp::Gt => compare(">"),
+        BuiltinOp::Ge => compare(">="),
+        BuiltinOp::Eq => compare("=="),
+        BuiltinOp::Ne => compare("!="),
+        BuiltinOp::Select => format!(
+            "if {} != 0.0 {{ {} }} else {{ {} }}",
+            args[0], args[1], args[2]
+        ),
+        // all remaining ones are unary methods with the same name
+        _ => format!("{}.{}()", args[0], op),
+    }
+}
+
+fn min(x: &str, y: &str) -> String {
+    format!("if {1} < {0} {{ {1} }} else {{ {0} }}", x, y)
+}
+
+fn max(x: &str, y: &str) -> String {
+    format!("if {1} > {0} {{ {1} }} else {{ {0} }}", x, y)
+}
+
+// strict and reserved keywords of all editions
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "crate",
+    "do", "dyn", "else", "enum", "extern", "false", "final", "fn", "for", "gen", "if", "impl",
+    "in", "let", "loop", "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+fn check_identifier(name: &str) -> Result<(), CompileError> {
+    // reserved for intermediate values
+    let is_temporary =
+        name.len() > 1 && name.starts_with('v') && name[1..].bytes().all(|c| c.is_ascii_digit());
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && name != "_"
+        && !KEYWORDS.contains(&name)
+        && !is_temporary;
+    if valid {
+        Ok(())
+    } else {
+        Err(CompileError::InvalidIdentifier(name.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let mut graph = CompGraph::<f32>::new();
+        let x1 = graph.add_input_node("x1");
+        let x2 = graph.add_input_node("x2");
+        let x3 = graph.add_input_node("x3");
+        let sin = graph.add_op(BuiltinOp::Sin, [x3]);
+        let max = graph.add_op(BuiltinOp::Max, [x2, sin]);
+        let result = graph.add_op(BuiltinOp::Lt, [x1, max]);
+
+        assert_eq!(
+            graph.to_rust_fn("eval", result).unwrap(),
+            "pub fn eval(x1: f32, x2: f32, x3: f32) -> f32 {
+    let v3 = x3.sin();
+    let v4 = if v3 > x2 { v3 } else { x2 };
+    let v5 = if x1 < v4 { 1.0 } else { 0.0 };
+    v5
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_constants() {
+        let mut graph = CompGraph::<f32>::new();
+        let x = graph.add_input_node("x");
+        let scale = graph.add_parameter("scale", 0.1);
+        let infinity = graph.add_constant(f32::NEG_INFINITY);
+        let scaled = graph.add_op(BuiltinOp::Mul, [x, scale]);
+        let result = graph.add_op(BuiltinOp::Max, [scaled, infinity]);
+
+        assert_eq!(
+            graph.to_rust_fn("eval", result).unwrap(),
+            "pub fn eval(x: f32) -> f32 {
+    let v1: f32 = 0.1;
+    let v3 = x * v1;
+    let v2: f32 = f32::NEG_INFINITY;
+    let v4 = if v2 > v3 { v2 } else { v3 };
+    v4
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_invalid_identifier() {
+        let mut graph = CompGraph::<f64>::new();
+        let input = graph.add_input_node("v1");
+        let result = graph.add_op(BuiltinOp::Neg, [input]);
+        assert!(matches!(
+            graph.to_rust_fn("eval", result),
+            Err(CompileError::InvalidIdentifier(name)) if name == "v1"
+        ));
+        assert!(matches!(
+            graph.to_rust_fn("fn", result),
+            Err(CompileError::InvalidIdentifier(name)) if name == "fn"
+        ));
+
+        let mut graph = CompGraph::<f64>::new();
+        let reserved = graph.add_input_node("yield");
+        assert!(matches!(
+            graph.to_rust_fn("eval", reserved),
+            Err(CompileError::InvalidIdentifier(name)) if name == "yield"
+        ));
+        let x = graph.add_input_node("x");
+        let other_x = graph.add_input_node("x");
+        let result = graph.add_op(BuiltinOp::Add, [x, other_x]);
+        assert!(matches!(
+            graph.to_rust_fn("eval", result),
+            Err(CompileError::DuplicateInput(name)) if name == "x"
+        ));
+    }
+
+    #[test]
+    fn test_clamp() {
+        let mut graph = CompGraph::<f32>::new();
+        let x = graph.add_input_node("x");
+        let low = graph.add_input_node("low");
+        let high = graph.add_input_node("high");
+        let result = graph.add_op(BuiltinOp::Clamp, [x, low, high]);
+
+        assert_eq!(
+            graph.to_rust_fn("eval", result).unwrap(),
+            "pub fn eval(x: f32, low: f32, high: f32) -> f32 {
+    let v3 = if high < (if low > x { low } else { x }) { high } else { if low > x { low } else { x } };
+    v3
+}
+"
+        );
+    }
+}
@@ -0,0 +1,345 @@
+use super::{CompGraph, NodeId, OpKind};
+use crate::ops::{BuiltinOp, Scalar};
+use std::fmt::{Display, Formatter};
+
+/// Error of [`CompGraph::differentiate`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DiffError {
+    /// Derivative can be taken only with respect to an input or a parameter
+    NotAVariable(NodeId),
+    /// Node depends on the variable through an operation that is not builtin
+    NotDifferentiable(NodeId),
+}
+
+impl Display for DiffError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffError::NotAVariable(node) => {
+                write!(f, "node {} is not an input or a parameter", node.0)
+            }
+            DiffError::NotDifferentiable(node) => {
+                write!(f, "operation of node {} is not builtin", node.0)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DiffError {}
+
+/// Derivative of a node, known constants are kept symbolic to avoid adding trivial nodes
+#[derive(Copy, Clone, PartialEq)]
+enum Derivative {
+    Zero,
+    One,
+    Node(NodeId),
+}
+
+use Derivative::{Node, One, Zero};
+
+impl<T: Scalar> CompGraph<T> {
+    /// Adds nodes computing derivative of `output` with respect to `wrt`, returns the last one.
+    ///
+    /// Derivatives are ordinary nodes, so they are cached, recomputed when inputs change
+    /// and can be differentiated again. Comparisons are treated as constants,
+    /// and derivatives of select, min, max, abs and clamp are the ones of the chosen branch.
+    ///
+    /// Nothing is added to the graph if an error is returned.
+    pub fn differentiate(&mut self, output: NodeId, wrt: NodeId) -> Result<NodeId, DiffError> {
+        if !matches!(
+            self.nodes[wrt.0].kind,
+            OpKind::Input(_) | OpKind::Constant(Some(_))
+        ) {
+            return Err(DiffError::NotAVariable(wrt));
+        }
+
+        let order = self.postorder(output);
+        let mut depends = vec![false; self.nodes.len()];
+        for &node in &order {
+            let current = &self.nodes[node];
+            depends[node] =
+                node == wrt.0 || current.node_inputs.iter().any(|input| depends[input.0]);
+            if depends[node] && node != wrt.0 && !matches!(current.kind, OpKind::Builtin(_)) {
+                return Err(DiffError::NotDifferentiable(NodeId(node)));
+            }
+        }
+
+        let mut derivatives = vec![Zero; self.nodes.len()];
+        for node in order {
+            let node = NodeId(node);
+            let inputs = self.nodes[node.0].node_inputs.clone();
+            let input_derivatives = inputs
+                .iter()
+                .map(|input| derivatives[input.0])
+                .collect::<Vec<_>>();
+            derivatives[node.0] = if node == wrt {
+                One
+            } else if input_derivatives.iter().all(|&d| d == Zero) {
+                Zero
+            } else {
+                match self.nodes[node.0].kind {
+                    OpKind::Builtin(op) => self.derive(op, node, &inputs, &input_derivatives),
+                    _ => unreachable!("operations depending on the variable should be builtin"),
+                }
+            };
+        }
+        Ok(self.materialize(derivatives[output.0]))
+    }
+
+    /// Derivative of `node` applying `op` to `inputs` with derivatives `d`
+    fn derive(
+        &mut self,
+        op: BuiltinOp,
+        node: NodeId,
+        inputs: &[NodeId],
+        d: &[Derivative],
+    ) -> Derivative {
+        match (op, inputs, d) {
+            (BuiltinOp::Add, _, &[du, dv]) => self.d_add(du, dv),
+            (BuiltinOp::Sub, _, &[du, dv]) => self.d_sub(du, dv),
+            (BuiltinOp::Mul, &[u, v], &[du, dv]) => {
+                let left = self.d_mul(du, Node(v));
+                let right = self.d_mul(Node(u), dv);
+                self.d_add(left, right)
+            }
+            (BuiltinOp::Div, &[u, v], &[du, dv]) => {
+                // u'/v - u*v'/v^2
+                let left = self.d_div(du, v);
+                let product = self.d_mul(Node(u), dv);
+                let square = self.add_op(BuiltinOp::Mul, [v, v]);
+                let right = self.d_div(product, square);
+                self.d_sub(left, right)
+            }
+            (BuiltinOp::Neg, _, &[du]) => self.d_neg(du),
+            (BuiltinOp::Sin, &[u], &[du]) => {
+                let cos = self.add_op(BuiltinOp::Cos, [u]);
+                self.d_mul(Node(cos), du)
+            }
+            (BuiltinOp::Cos, &[u], &[du]) => {
+                let sin = self.add_op(BuiltinOp::Sin, [u]);
+                let product = self.d_mul(Node(sin), du);
+                self.d_neg(product)
+            }
+            (BuiltinOp::Tan, &[u], &[du]) => {
+                let cos = self.add_op(BuiltinOp::Cos, [u]);
+                let square = self.add_op(BuiltinOp::Mul, [cos, cos]);
+                self.d_div(du, square)
+            }
+            (BuiltinOp::Exp, _, &[du]) => self.d_mul(Node(node), du),
+            (BuiltinOp::Ln, &[u], &[du]) => self.d_div(du, u),
+            (BuiltinOp::Sqrt, _, &[du]) => {
+                let two = self.add_constant(T::ONE + T::ONE);
+                let double = self.add_op(BuiltinOp::Mul, [two, node]);
+                self.d_div(du, double)
+            }
+            (BuiltinOp::Pow, &[u, v], &[du, Zero]) => {
+                // v * u^(v - 1) * u', not folding constant `v` as it can still be changed
+                let one = self.add_constant(T::ONE);
+                let exponent = self.add_op(BuiltinOp::Sub, [v, one]);
+                let power = self.add_op(BuiltinOp::Pow, [u, exponent]);
+                let scaled = self.add_op(BuiltinOp::Mul, [v, power]);
+                self.d_mul(Node(scaled), du)
+            }
+            (BuiltinOp::Pow, &[u, v], &[du, dv]) => {
+                // u^v * (v' * ln(u) + v * u' / u)
+                let ln = self.add_op(BuiltinOp::Ln, [u]);
+                let left = self.d_mul(dv, Node(ln));
+                let ratio = self.d_div(du, u);
+                let right = self.d_mul(Node(v), ratio);
+                let sum = self.d_add(left, right);
+                self.d_mul(Node(node), sum)
+            }
+            (BuiltinOp::Min, &[u, v], &[du, dv]) => self.d_min(u, v, du, dv),
+            (BuiltinOp::Max, &[u, v], &[du, dv]) => self.d_max(u, v, du, dv),
+            (BuiltinOp::Abs, &[u], &[du]) => {
+                let zero = self.add_constant(T::ZERO);
+                let negative = self.add_op(BuiltinOp::Lt, [u, zero]);
+                let negated = self.d_neg(du);
+                self.d_select(negative, negated, du)
+            }
+            (BuiltinOp::Clamp, &[x, low, high], &[dx, dlow, dhigh]) => {
+                let max = self.add_op(BuiltinOp::Max, [x, low]);
+                let dmax = self.d_max(x, low, dx, dlow);
+                self.d_min(max, high, dmax, dhigh)
+            }
+            (BuiltinOp::Select, &[condition, ..], &[_, da, db]) => self.d_select(condition, da, db),
+            // comparisons are piecewise constant
+            _ => Zero,
+        }
+    }
+
+    fn materialize(&mut self, derivative: Derivative) -> NodeId {
+        match derivative {
+            Zero => self.add_constant(T::ZERO),
+            One => self.add_constant(T::ONE),
+            Node(node) => node,
+        }
+    }
+
+    fn d_add(&mut self, a: Derivative, b: Derivative) -> Derivative {
+        match (a, b) {
+            (Zero, d) | (d, Zero) => d,
+            (a, b) => {
+                let (a, b) = (self.materialize(a), self.materialize(b));
+                Node(self.add_op(BuiltinOp::Add, [a, b]))
+            }
+        }
+    }
+
+    fn d_sub(&mut self, a: Derivative, b: Derivative) -> Derivative {
+        match (a, b) {
+            (d, Zero) => d,
+            (Zero, d) => self.d_neg(d),
+            (a, b) => {
+                let (a, b) = (self.materialize(a), self.materialize(b));
+                Node(self.add_op(BuiltinOp::Sub, [a, b]))
+            }
+        }
+    }
+
+    fn d_neg(&mut self, d: Derivative) -> Derivative {
+        match d {
+            Zero => Zero,
+            d => {
+                let d = self.materialize(d);
+                Node(self.add_op(BuiltinOp::Neg, [d]))
+            }
+        }
+    }
+
+    fn d_mul(&mut self, a: Derivative, b: Derivative) -> Derivative {
+        match (a, b) {
+            (Zero, _) | (_, Zero) => Zero,
+            (One, d) | (d, One) => d,
+            (Node(a), Node(b)) => Node(self.add_op(BuiltinOp::Mul, [a, b])),
+        }
+    }
+
+    fn d_div(&mut self, d: Derivative, divisor: NodeId) -> Derivative {
+        match d {
+            Zero => Zero,
+            d => {
+                let d = self.materialize(d);
+                Node(self.add_op(BuiltinOp::Div, [d, divisor]))
+            }
+        }
+    }
+
+    fn d_select(&mut self, condition: NodeId, a: Derivative, b: Derivative) -> Derivative {
+        if a == b {
+            return a;
+        }
+        let (a, b) = (self.materialize(a), self.materialize(b));
+        Node(self.add_op(BuiltinOp::Select, [condition, a, b]))
+    }
+
+    /// Derivative of `min(u, v)`, which is `v` only if it is less
+    fn d_min(&mut self, u: NodeId, v: NodeId, du: Derivative, dv: Derivative) -> Derivative {
+        let less = self.add_op(BuiltinOp::Lt, [v, u]);
+        self.d_select(less, dv, du)
+    }
+
+    /// Derivative of `max(u, v)`, which is `v` only if it is greater
+    fn d_max(&mut self, u: NodeId, v: NodeId, du: Derivative, dv: Derivative) -> Derivative {
+        let greater = self.add_op(BuiltinOp::Gt, [v, u]);
+        self.d_select(greater, dv, du)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_differentiate() {
+        let mut graph = CompGraph::new();
+        let x1 = graph.add_input_node("x1");
+        let x2 = graph.add_input_node("x2");
+        let x3 = graph.add_input_node("x3");
+        let three = graph.add_constant(3.0);
+        let cube = graph.add_op(BuiltinOp::Pow, [x3, three]);
+        let sum = graph.add_op(BuiltinOp::Add, [x2, cube]);
+        let sin = graph.add_op(BuiltinOp::Sin, [sum]);
+        let product = graph.add_op(BuiltinOp::Mul, [x2, sin]);
+        let result = graph.add_op(BuiltinOp::Add, [x1, product]);
+        graph.set_input("x1", 1.0f64);
+        graph.set_input("x2", 2.0);
+        graph.set_input("x3", 3.0);
+
+        let d_x3 = graph.differentiate(result, x3).unwrap();
+        assert_eq!(
+            graph.node(d_x3).to_string(),
+            "x2 * (cos(x2 + x3^3) * (3 * x3^(3 - 1)))"
+        );
+        let expected = |x2: f64, x3: f64| x2 * (x2 + x3.powi(3)).cos() * 3.0 * x3 * x3;
+        assert!((graph.compute(d_x3) - expected(2.0, 3.0)).abs() < 1e-9);
+        graph.set_input("x2", 0.5);
+        assert!((graph.compute(d_x3) - expected(0.5, 3.0)).abs() < 1e-9);
+        graph.set_constant(three, 2.0);
+        let expected = |x2: f64, x3: f64| x2 * (x2 + x3 * x3).cos() * 2.0 * x3;
+        assert!((graph.compute(d_x3) - expected(0.5, 3.0)).abs() < 1e-9);
+
+        let d_x1 = graph.differentiate(result, x1).unwrap();
+        assert_eq!(graph.compute(d_x1), 1.0);
+        let d_x2 = graph.differentiate(x3, x2).unwrap();
+        assert_eq!(graph.compute(d_x2), 0.0);
+    }
+
+    #[test]
+    fn test_higher_order() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let scale = graph.add_parameter("scale", 2.0);
+        let scaled = graph.add_op(BuiltinOp::Mul, [scale, x]);
+        let result = graph.add_op(BuiltinOp::Exp, [scaled]);
+        graph.set_input("x", 0.5f64);
+
+        let first = graph.differentiate(result, x).unwrap();
+        let second = graph.differentiate(first, x).unwrap();
+        assert!((graph.compute(second) - 4.0 * 1f64.exp()).abs() < 1e-9);
+        let by_scale = graph.differentiate(result, scale).unwrap();
+        assert!((graph.compute(by_scale) - 0.5 * 1f64.exp()).abs() < 1e-9);
+        graph.set_constant(scale, 1.0);
+        assert!((graph.compute(second) - 0.5f64.exp()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_branches() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let low = graph.add_constant(-1.0);
+        let high = graph.add_constant(1.0);
+        let clamped = graph.add_op(BuiltinOp::Clamp, [x, low, high]);
+        let abs = graph.add_op(BuiltinOp::Abs, [x]);
+        let result = graph.add_op(BuiltinOp::Mul, [clamped, abs]);
+        let derivative = graph.differentiate(result, x).unwrap();
+
+        for (value, expected) in [(-2.0, 1.0), (-0.25, 0.5), (0.25, 0.5), (2.0, 1.0)] {
+            graph.set_input("x", value);
+            assert_eq!(graph.compute(derivative), expected);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut graph = CompGraph::<f32>::new();
+        let x = graph.add_input_node("x");
+        let y = graph.add_input_node("y");
+        let custom = graph.add_node([x], |args| args.next().unwrap() * 2.0);
+        let sin = graph.add_op(BuiltinOp::Sin, [x]);
+        let sum = graph.add_op(BuiltinOp::Add, [sin, custom]);
+        let result = graph.add_op(BuiltinOp::Add, [sum, y]);
+
+        let node_count = graph.nodes.len();
+        assert_eq!(
+            graph.differentiate(result, x),
+            Err(DiffError::NotDifferentiable(custom))
+        );
+        // the derivative of `sin` is not added
+        assert_eq!(graph.nodes.len(), node_count);
+        assert!(graph.differentiate(result, y).is_ok());
+        assert_eq!(
+            graph.differentiate(result, custom),
+            Err(DiffError::NotAVariable(custom))
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use super::{CompGraph, OpKind};
+use std::fmt::Write;
+
+impl<T> CompGraph<T> {
+    /// Renders graph in Graphviz DOT format with edges going from inputs to their dependents.
+    ///
+    /// Nodes are shown by their labels, or names of inputs and operations if they have none.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+        for (id, node) in self.nodes.iter().enumerate() {
+            let text = match (&node.label, node.kind.name(), &node.kind) {
+                (Some(label), _, _) => label.to_string(),
+                (None, Some(name), _) => name.to_owned(),
+                (None, None, OpKind::Port(index)) => format!("output {}", index),
+                (None, None, _) => format!("#{}", id),
+            };
+            let shape = match node.kind {
+                OpKind::Input(_) => ", shape=box",
+                OpKind::Constant(_) => ", shape=box, style=dashed",
+                _ => "",
+            };
+            writeln!(dot, "    n{} [label={:?}{}];", id, text, shape).unwrap();
+            for input in node.node_inputs.iter() {
+                writeln!(dot, "    n{} -> n{};", input.0, id).unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::BuiltinOp;
+
+    #[test]
+    fn test_to_dot() {
+        let mut graph = CompGraph::<f64>::new();
+        let x = graph.add_input_node("x");
+        let rate = graph.add_parameter("rate", 0.05);
+        let factor = graph.add_constant(1.0);
+        let discount = graph.add_op(BuiltinOp::Add, [factor, rate]);
+        let result = graph.add_node([x, discount], |args| {
+            args.next().unwrap() / args.next().unwrap()
+        });
+        graph.set_label(result, "discounted \"cashflow\"");
+
+        assert_eq!(
+            graph.to_dot(),
+            "digraph {\n    \
+                n0 [label=\"x\", shape=box];\n    \
+                n1 [label=\"rate\", shape=box, style=dashed];\n    \
+                n2 [label=\"#2\", shape=box, style=dashed];\n    \
+                n3 [label=\"add\"];\n    \
+                n2 -> n3;\n    \
+                n1 -> n3;\n    \
+                n4 [label=\"discounted \\\"cashflow\\\"\"];\n    \
+                n0 -> n4;\n    \
+                n3 -> n4;\n\
+            }\n"
+        );
+    }
+}
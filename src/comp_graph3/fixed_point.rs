@@ -0,0 +1,287 @@
+use super::subgraph::Instance;
+use super::{CompGraph, NodeId, SubGraph};
+use crate::ops::Scalar;
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
+
+/// Parameters of loop nodes created by [`CompGraph::add_loop_node`]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LoopOptions {
+    /// How many times the body can be evaluated before giving up
+    pub max_iterations: usize,
+    /// Loop stops when no state value changes by more than this in one iteration
+    pub tolerance: f64,
+}
+
+impl Default for LoopOptions {
+    fn default() -> Self {
+        Self {
+            max_iterations: 100,
+            tolerance: 1e-9,
+        }
+    }
+}
+
+/// Error of computing a node with [`CompGraph::try_compute`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum EvalError {
+    /// Loop node didn't converge within its maximum number of iterations
+    NonConvergence {
+        node: NodeId,
+        iterations: usize,
+        /// Largest change of a state value in the last iteration
+        residual: f64,
+    },
+    /// Node of the subgraph of a call or a loop body failed
+    Call {
+        node: NodeId,
+        source: Box<EvalError>,
+    },
+}
+
+impl EvalError {
+    /// Node that failed
+    pub fn node(&self) -> NodeId {
+        match self {
+            EvalError::NonConvergence { node, .. } | EvalError::Call { node, .. } => *node,
+        }
+    }
+}
+
+/// Records `err` of the evaluation in progress, replacing an earlier error of the same node,
+/// e.g. from a previous iteration of a loop
+pub(super) fn report(errors: &RefCell<Vec<EvalError>>, err: EvalError) {
+    let mut errors = errors.borrow_mut();
+    match errors.iter_mut().find(|other| other.node() == err.node()) {
+        Some(other) => *other = err,
+        None => errors.push(err),
+    }
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalError::NonConvergence {
+                node,
+                iterations,
+                residual,
+            } => write!(
+                f,
+                "loop of node {} did not converge after {} iterations, last change was {}",
+                node.0, iterations, residual
+            ),
+            EvalError::Call { node, source } => {
+                write!(f, "subgraph of node {} failed: {}", node.0, source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EvalError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EvalError::Call { source, .. } => Some(&**source),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Scalar> CompGraph<T> {
+    /// Adds node iterating `body` until its state converges, returns nodes of the final state.
+    ///
+    /// The first parameters of `body` are the state, one for each of its outputs,
+    /// starting with values of `initial` and replaced by the outputs after every iteration.
+    /// The remaining parameters get values of `args`, which stay the same during the loop.
+    ///
+    /// If the state doesn't converge within `options.max_iterations`, or a change of it
+    /// is not finite, [`try_compute`](Self::try_compute) of dependent nodes
+    /// reports [`EvalError::NonConvergence`].
+    pub fn add_loop_node(
+        &mut self,
+        body: &SubGraph<T>,
+        initial: impl IntoIterator<Item = NodeId>,
+        args: impl IntoIterator<Item = NodeId>,
+        options: LoopOptions,
+    ) -> Vec<NodeId> {
+        let mut instance = Instance::new(body);
+        let state_size = instance.output_count();
+        let inputs = initial.into_iter().chain(args).collect::<Vec<_>>();
+        assert!(
+            state_size <= inputs.len(),
+            "loop state should have initial value for every output of the body"
+        );
+        assert_eq!(
+            inputs.len(),
+            instance.param_count(),
+            "wrong number of arguments for loop body"
+        );
+        // the node computing the loop is added first by `add_multi_output_node`
+        let node = NodeId(self.nodes.len());
+        let eval_errors = self.eval_errors.clone();
+
+        self.add_multi_output_node(inputs, state_size, move |values| {
+            let values = values.collect::<Vec<_>>();
+            let (mut state, args) = {
+                let (state, args) = values.split_at(state_size);
+                (state.to_vec(), args)
+            };
+            let mut residual = f64::INFINITY;
+            let mut iterations = 0;
+            while iterations < options.max_iterations {
+                let next = instance.eval(state.iter().chain(args).copied(), node, &eval_errors);
+                iterations += 1;
+                // NaN is kept, unlike with `f64::max`
+                residual = state
+                    .iter()
+                    .zip(&next)
+                    .map(|(&old, &new)| (new - old).abs().to_f64())
+                    .fold(0.0, |max, change| if change <= max { max } else { change });
+                state = next;
+                if residual <= options.tolerance {
+                    return state;
+                }
+                if !residual.is_finite() {
+                    break;
+                }
+            }
+            report(
+                &eval_errors,
+                EvalError::NonConvergence {
+                    node,
+                    iterations,
+                    residual,
+                },
+            );
+            state
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::BuiltinOp;
+
+    /// Newton's method for square root of `a`
+    fn sqrt_step() -> SubGraph<f64> {
+        SubGraph::new(["x", "a"], ["next"], |graph, params| {
+            let [x, a] = params else { unreachable!() };
+            let quotient = graph.add_op(BuiltinOp::Div, [*a, *x]);
+            let sum = graph.add_op(BuiltinOp::Add, [*x, quotient]);
+            vec![graph.add_node([sum], |sum| sum.next().unwrap() / 2.0)]
+        })
+    }
+
+    #[test]
+    fn test_loop() {
+        let mut graph = CompGraph::new();
+        let guess = graph.add_input_node("guess");
+        let a = graph.add_input_node("a");
+        let sqrt = graph.add_loop_node(&sqrt_step(), [guess], [a], LoopOptions::default())[0];
+        let result = graph.add_op(BuiltinOp::Neg, [sqrt]);
+        graph.set_input("guess", 1.0);
+        graph.set_input("a", 2.0);
+
+        assert!((graph.try_compute(result).unwrap() + 2f64.sqrt()).abs() < 1e-9);
+        graph.set_input("a", 9.0);
+        assert!((graph.compute(sqrt) - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_non_convergence() {
+        let mut graph = CompGraph::new();
+        let start = graph.add_input_node("start");
+        let body = SubGraph::new(["x"], ["next"], |graph, params| {
+            vec![graph.add_op(BuiltinOp::Neg, params.iter().copied())]
+        });
+        let options = LoopOptions {
+            max_iterations: 10,
+            ..Default::default()
+        };
+        let oscillating = graph.add_loop_node(&body, [start], [], options)[0];
+        let result = graph.add_op(BuiltinOp::Neg, [oscillating]);
+        graph.set_input("start", 1.0f32);
+
+        let err = graph.try_compute(result).unwrap_err();
+        assert!(matches!(
+            err,
+            EvalError::NonConvergence {
+                iterations: 10,
+                residual: 2.0,
+                ..
+            }
+        ));
+        assert_eq!(graph.cache(result), None);
+        assert_eq!(graph.try_compute(result), Err(err));
+
+        graph.set_input("start", 0.0);
+        assert_eq!(graph.try_compute(result), Ok(0.0));
+    }
+
+    #[test]
+    fn test_several_failures() {
+        let mut graph = CompGraph::new();
+        let body = SubGraph::new(["x"], ["next"], |graph, params| {
+            vec![graph.add_op(BuiltinOp::Neg, params.iter().copied())]
+        });
+        let a = graph.add_input_node("a");
+        let b = graph.add_input_node("b");
+        let la = graph.add_loop_node(&body, [a], [], LoopOptions::default())[0];
+        let lb = graph.add_loop_node(&body, [b], [], LoopOptions::default())[0];
+        let sum = graph.add_op(BuiltinOp::Add, [la, lb]);
+        graph.set_input("a", 1.0f64);
+        graph.set_input("b", 1.0);
+
+        assert!(graph.try_compute(sum).is_err());
+        assert_eq!(graph.cache(la), None);
+        assert_eq!(graph.cache(lb), None);
+        // the loop of `la` still fails after fixing the other one
+        graph.set_input("b", 0.0);
+        let err = graph.try_compute(sum).unwrap_err();
+        assert_eq!(err.node(), graph.node(la).inputs()[0]);
+        graph.set_input("a", 0.0);
+        assert_eq!(graph.try_compute(sum), Ok(0.0));
+    }
+
+    #[test]
+    fn test_non_finite_residual() {
+        let mut graph = CompGraph::new();
+        let start = graph.add_input_node("start");
+        let body = SubGraph::new(["x"], ["next"], |graph, params| {
+            vec![graph.add_op(BuiltinOp::Sqrt, params.iter().copied())]
+        });
+        let root = graph.add_loop_node(&body, [start], [], LoopOptions::default())[0];
+        graph.set_input("start", -1.0f64);
+
+        let err = graph.try_compute(root).unwrap_err();
+        assert!(matches!(
+            err,
+            EvalError::NonConvergence { iterations: 1, residual, .. } if residual.is_nan()
+        ));
+    }
+
+    #[test]
+    fn test_failed_call() {
+        let body = SubGraph::new(["x"], ["next"], |graph, params| {
+            vec![graph.add_op(BuiltinOp::Neg, params.iter().copied())]
+        });
+        let oscillating = SubGraph::new(["start"], ["result"], move |graph, params| {
+            graph.add_loop_node(&body, params.iter().copied(), [], LoopOptions::default())
+        });
+        let mut graph = CompGraph::new();
+        let start = graph.add_input_node("start");
+        let result = graph.call(&oscillating, [start])[0];
+        let call = graph.node(result).inputs()[0];
+        graph.set_input("start", 1.0f64);
+
+        let err = graph.try_compute(result).unwrap_err();
+        assert_eq!(err.node(), call);
+        assert!(matches!(
+            &err,
+            EvalError::Call { source, .. } if matches!(**source, EvalError::NonConvergence { .. })
+        ));
+        assert_eq!(graph.try_compute(result), Err(err));
+        graph.set_input("start", 0.0);
+        assert_eq!(graph.try_compute(result), Ok(0.0));
+    }
+}
@@ -0,0 +1,260 @@
+use super::{CompGraph, NodeId, OpKind};
+use crate::pretty::{self, Expression, Term};
+use crate::stats::CacheStats;
+use std::fmt::{Debug, Display, Formatter};
+
+/// Read-only view of a node of [`CompGraph`]
+pub struct NodeRef<'g, T> {
+    graph: &'g CompGraph<T>,
+    id: NodeId,
+}
+
+impl<T> Clone for NodeRef<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for NodeRef<'_, T> {}
+
+impl<'g, T> NodeRef<'g, T> {
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+
+    pub fn kind(&self) -> &'g OpKind {
+        &self.graph.nodes[self.id.0].kind
+    }
+
+    /// Name of the input, parameter or operation, if it has one
+    pub fn name(&self) -> Option<&'g str> {
+        self.kind().name()
+    }
+
+    pub fn label(&self) -> Option<&'g str> {
+        self.graph.nodes[self.id.0].label.as_deref()
+    }
+
+    /// Nodes whose values the operation takes, in order
+    pub fn inputs(&self) -> &'g [NodeId] {
+        &self.graph.nodes[self.id.0].node_inputs
+    }
+
+    /// Nodes invalidated when this one changes.
+    ///
+    /// Selects that chose the other branch the last time they were computed are not included.
+    pub fn dependents(&self) -> impl Iterator<Item = NodeId> + 'g {
+        self.graph.nodes[self.id.0]
+            .dependents
+            .iter()
+            .map(|&dependent| NodeId(dependent))
+    }
+
+    /// Number of values the operation produces, read by [`OpKind::Port`] nodes
+    /// created with it, 1 for nodes with a single output
+    pub fn output_count(&self) -> usize {
+        self.graph.output_count(self.id)
+    }
+
+    /// Cached value, always present for set inputs and constants
+    pub fn cached(&self) -> Option<&'g T> {
+        self.graph.nodes[self.id.0].cache.as_ref()
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        self.graph.nodes[self.id.0].stats
+    }
+}
+
+impl<T: Debug> Debug for NodeRef<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NodeRef")
+            .field("id", &self.id.0)
+            .field("kind", self.kind())
+            .field("label", &self.label())
+            .field("inputs", &self.inputs())
+            .field("cached", &self.cached())
+            .finish()
+    }
+}
+
+impl<'g, T: Display> Expression<'g> for NodeRef<'g, T> {
+    fn id(&self) -> usize {
+        self.id.0
+    }
+
+    fn term(&self) -> Term<'_> {
+        match self.kind() {
+            OpKind::Input(name) | OpKind::Constant(Some(name)) => Term::Atom(name.as_ref().into()),
+            OpKind::Constant(None) => Term::Atom(
+                self.cached()
+                    .expect("constant should always be cached")
+                    .to_string()
+                    .into(),
+            ),
+            &OpKind::Builtin(op) => Term::Builtin(op),
+            OpKind::Named(name) => Term::Call(name.as_ref().into()),
+            OpKind::Custom => Term::Call("custom".into()),
+            OpKind::Port(index) => Term::Call(format!("output{}", index).into()),
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        NodeRef::label(self)
+    }
+
+    fn for_each_input(&self, f: &mut dyn FnMut(Box<dyn Expression<'g> + 'g>)) {
+        for &input in self.inputs() {
+            f(Box::new(self.graph.node(input)));
+        }
+    }
+}
+
+/// Prints the node as an infix expression, see [`pretty`](crate::pretty)
+impl<T: Display> Display for NodeRef<'_, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        pretty::write(f, self)
+    }
+}
+
+impl<T: Debug> Debug for CompGraph<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.nodes()).finish()
+    }
+}
+
+impl NodeId {
+    /// Position of the node in the graph, ids are assigned consecutively from 0
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+impl<T> CompGraph<T> {
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn node(&self, id: NodeId) -> NodeRef<'_, T> {
+        assert!(id.0 < self.nodes.len(), "node is not in the graph");
+        NodeRef { graph: self, id }
+    }
+
+    /// All nodes in order of their creation
+    pub fn nodes(&self) -> impl Iterator<Item = NodeRef<'_, T>> {
+        (0..self.nodes.len()).map(|id| NodeRef {
+            graph: self,
+            id: NodeId(id),
+        })
+    }
+
+    /// Names and ids of input nodes in order of their creation
+    pub fn input_nodes(&self) -> impl Iterator<Item = (&str, NodeId)> {
+        let mut inputs = self
+            .graph_inputs
+            .iter()
+            .map(|(name, &id)| (&**name, NodeId(id)))
+            .collect::<Vec<_>>();
+        inputs.sort_by_key(|(_, id)| id.0);
+        inputs.into_iter()
+    }
+
+    /// Id of input `name`
+    pub fn input_node(&self, name: &str) -> Option<NodeId> {
+        self.graph_inputs.get(name).map(|&id| NodeId(id))
+    }
+
+    /// Id of the first node labeled `name`, or if there is none,
+    /// the first node whose input, parameter or operation name is `name`
+    pub fn find_node(&self, name: &str) -> Option<NodeId> {
+        self.nodes()
+            .find(|node| node.label() == Some(name))
+            .or_else(|| self.nodes().find(|node| node.name() == Some(name)))
+            .map(|node| node.id)
+    }
+
+    /// Ids of all nodes, each one after its inputs
+    pub fn topological_order(&self) -> Vec<NodeId> {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut order = Vec::with_capacity(self.nodes.len());
+        for root in 0..self.nodes.len() {
+            self.visit_postorder(root, &mut visited, &mut order);
+        }
+        order.into_iter().map(NodeId).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::BuiltinOp;
+
+    #[test]
+    fn test_introspection() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let y = graph.add_input_node("y");
+        let scale = graph.add_parameter("scale", 2.0);
+        let product = graph.add_op(BuiltinOp::Mul, [x, scale]);
+        let result = graph.add_op(BuiltinOp::Add, [product, y]);
+        graph.set_input("x", 1.0f64);
+        graph.set_input("y", 2.0);
+        graph.compute(product);
+
+        assert_eq!(graph.node_count(), 5);
+        assert_eq!(
+            graph.input_nodes().collect::<Vec<_>>(),
+            [("x", x), ("y", y)]
+        );
+        assert_eq!(graph.input_node("y"), Some(y));
+        assert_eq!(graph.input_node("scale"), None);
+        assert_eq!(graph.find_node("scale"), Some(scale));
+        assert_eq!(graph.find_node("add"), Some(result));
+        graph.set_label(scale, "add");
+        assert_eq!(graph.find_node("add"), Some(scale));
+
+        let node = graph.node(product);
+        assert_eq!(node.kind(), &OpKind::Builtin(BuiltinOp::Mul));
+        assert_eq!(node.name(), Some("mul"));
+        assert_eq!(node.inputs(), [x, scale]);
+        assert_eq!(node.dependents().collect::<Vec<_>>(), [result]);
+        assert_eq!(node.cached(), Some(&2.0));
+        assert_eq!(graph.node(result).cached(), None);
+        assert_eq!(node.cache_stats().misses, 1);
+        let names = graph.nodes().map(|node| node.name()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                Some("x"),
+                Some("y"),
+                Some("scale"),
+                Some("mul"),
+                Some("add")
+            ]
+        );
+        assert_eq!(
+            format!("{:?}", graph.node(x)),
+            "NodeRef { id: 0, kind: Input(\"x\"), label: None, inputs: [], cached: Some(1.0) }"
+        );
+    }
+
+    #[test]
+    fn test_topological_order() {
+        let mut graph = CompGraph::<f32>::new();
+        let x = graph.add_input_node("x");
+        let neg = graph.add_op(BuiltinOp::Neg, [x]);
+        let y = graph.add_input_node("y");
+        let sum = graph.add_op(BuiltinOp::Add, [x, y]);
+        graph.set_node_inputs(neg, [sum]).unwrap();
+
+        let order = graph.topological_order();
+        assert_eq!(order, [x, y, sum, neg]);
+        let position = |node: NodeId| order.iter().position(|&other| other == node).unwrap();
+        for node in graph.nodes() {
+            for &input in node.inputs() {
+                assert!(position(input) < position(node.id()));
+            }
+        }
+        assert_eq!(neg.index(), 1);
+    }
+}
@@ -0,0 +1,290 @@
+use super::{CompGraph, NodeId, NodeOp, OpKind, OpRegistry};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use smallvec::SmallVec;
+use std::fmt::{Display, Formatter};
+
+// version 2 added constants and version 3 ports, files of older versions are still readable
+const FORMAT_VERSION: u32 = 3;
+
+#[derive(Serialize, Deserialize)]
+struct GraphRepr<T> {
+    version: u32,
+    nodes: Vec<NodeRepr<T>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum NodeRepr<T> {
+    Input {
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<T>,
+    },
+    Constant {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        value: T,
+    },
+    Op {
+        op: String,
+        inputs: Vec<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<T>,
+    },
+    /// Output `index` of the node `input` with several outputs
+    Port {
+        input: usize,
+        index: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<T>,
+    },
+}
+
+/// Error of saving or loading graph as JSON
+#[derive(Debug)]
+pub enum JsonError {
+    Json(serde_json::Error),
+    /// Node was added with [`CompGraph::add_node`] so its operation can't be restored
+    AnonymousOp(NodeId),
+    UnsupportedVersion(u32),
+    /// Operation is not in the [`OpRegistry`]
+    UnknownOp(String),
+    /// Node refers to an input that doesn't exist or depends on the node
+    InvalidInput {
+        node: usize,
+        input: usize,
+    },
+    WrongArity {
+        node: usize,
+        op: String,
+    },
+    /// Several inputs have the same name, so only one of them could be set
+    DuplicateInput(String),
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::Json(err) => write!(f, "invalid json: {}", err),
+            JsonError::AnonymousOp(node) => {
+                write!(f, "operation of node {} has no name", node.0)
+            }
+            JsonError::UnsupportedVersion(version) => {
+                write!(f, "unsupported format version {}", version)
+            }
+            JsonError::UnknownOp(op) => write!(f, "unknown operation {}", op),
+            JsonError::InvalidInput { node, input } => {
+                write!(f, "node {} refers to invalid input {}", node, input)
+            }
+            JsonError::WrongArity { node, op } => {
+                write!(f, "wrong number of inputs for {} in node {}", op, node)
+            }
+            JsonError::DuplicateInput(name) => write!(f, "several inputs are named {}", name),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+impl From<serde_json::Error> for JsonError {
+    fn from(err: serde_json::Error) -> Self {
+        JsonError::Json(err)
+    }
+}
+
+impl<T: Clone + Serialize> CompGraph<T> {
+    /// Serializes graph into JSON.
+    ///
+    /// Values of inputs are always saved, cached values of other nodes only if `with_cache` is set.
+    pub fn to_json(&self, with_cache: bool) -> Result<String, JsonError> {
+        let nodes = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(id, node)| {
+                let op = match &node.kind {
+                    OpKind::Input(name) => {
+                        return Ok(NodeRepr::Input {
+                            name: name.to_string(),
+                            value: node.cache.clone(),
+                        })
+                    }
+                    OpKind::Constant(name) => {
+                        return Ok(NodeRepr::Constant {
+                            name: name.as_ref().map(|name| name.to_string()),
+                            value: node
+                                .cache
+                                .clone()
+                                .expect("constant should always be cached"),
+                        })
+                    }
+                    OpKind::Builtin(op) => op.name().to_owned(),
+                    OpKind::Named(name) => name.to_string(),
+                    OpKind::Custom => return Err(JsonError::AnonymousOp(NodeId(id))),
+                    &OpKind::Port(index) => {
+                        return Ok(NodeRepr::Port {
+                            input: node.node_inputs[0].0,
+                            index,
+                            value: node.cache.clone().filter(|_| with_cache),
+                        })
+                    }
+                };
+                Ok(NodeRepr::Op {
+                    op,
+                    inputs: node.node_inputs.iter().map(|input| input.0).collect(),
+                    value: node.cache.clone().filter(|_| with_cache),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(serde_json::to_string(&GraphRepr {
+            version: FORMAT_VERSION,
+            nodes,
+        })?)
+    }
+}
+
+impl<T: DeserializeOwned + 'static> CompGraph<T> {
+    /// Restores graph saved by [`to_json`](Self::to_json),
+    /// operations are looked up by name in `registry`
+    pub fn from_json(json: &str, registry: &OpRegistry<T>) -> Result<Self, JsonError> {
+        let repr: GraphRepr<T> = serde_json::from_str(json)?;
+        if repr.version == 0 || repr.version > FORMAT_VERSION {
+            return Err(JsonError::UnsupportedVersion(repr.version));
+        }
+
+        let mut graph = CompGraph::new();
+        // inputs can be defined after the node, so they are attached once all nodes exist
+        let mut wiring = vec![];
+        for (id, node) in repr.nodes.into_iter().enumerate() {
+            match node {
+                NodeRepr::Input { name, value } => {
+                    if graph.graph_inputs.contains_key(name.as_str()) {
+                        return Err(JsonError::DuplicateInput(name));
+                    }
+                    let input = graph.add_input_node(name);
+                    graph.nodes[input.0].cache = value;
+                }
+                NodeRepr::Constant { name, value } => {
+                    graph.push_constant(name.map(Into::into), value);
+                }
+                NodeRepr::Op { op, inputs, value } => {
+                    let node_inputs = inputs.into_iter().map(NodeId).collect::<SmallVec<_>>();
+                    let (kind, boxed) = registry
+                        .instantiate(&op)
+                        .ok_or_else(|| JsonError::UnknownOp(op.clone()))?;
+                    if let OpKind::Builtin(builtin) = kind {
+                        if builtin.arity() != node_inputs.len() {
+                            return Err(JsonError::WrongArity { node: id, op });
+                        }
+                    }
+                    // outputs other than the first one are not saved, so they are recomputed
+                    let is_multi = matches!(boxed, NodeOp::Multi { .. });
+                    let node = graph.push_node(SmallVec::new(), kind, boxed);
+                    graph.nodes[node.0].cache = value.filter(|_| !is_multi);
+                    wiring.push((node, node_inputs));
+                }
+                NodeRepr::Port {
+                    input,
+                    index,
+                    value,
+                } => {
+                    let node = graph.push_node(SmallVec::new(), OpKind::Port(index), NodeOp::None);
+                    graph.nodes[node.0].cache = value;
+                    wiring.push((node, SmallVec::from_elem(NodeId(input), 1)));
+                }
+            }
+        }
+        graph
+            .attach_inputs(wiring)
+            .map_err(|(node, input)| JsonError::InvalidInput {
+                node: node.0,
+                input: input.0,
+            })?;
+        if let Some((node, input)) = graph.invalid_port() {
+            return Err(JsonError::InvalidInput {
+                node: node.0,
+                input: input.0,
+            });
+        }
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::BuiltinOp;
+
+    fn sample_graph() -> (CompGraph<f64>, NodeId) {
+        let mut graph = CompGraph::new();
+        let x1 = graph.add_input_node("x1");
+        let x2 = graph.add_input_node("x2");
+        let sum = graph.add_op(BuiltinOp::Add, [x1, x2]);
+        let result = graph.add_named_node("double", [sum], double);
+        graph.set_input("x1", 1.0);
+        graph.set_input("x2", 2.0);
+        (graph, result)
+    }
+
+    fn double(args: &mut dyn Iterator<Item = f64>) -> f64 {
+        args.next().unwrap() * 2.0
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let (mut graph, result) = sample_graph();
+        assert_eq!(graph.compute(result), 6.0);
+
+        let mut registry = OpRegistry::with_builtins();
+        registry.register("double", double);
+
+        let json = graph.to_json(false).unwrap();
+        let mut restored = CompGraph::from_json(&json, &registry).unwrap();
+        assert_eq!(restored.cache(result), None);
+        assert_eq!(restored.compute(result), 6.0);
+        restored.set_input("x2", 3.0);
+        assert_eq!(restored.compute(result), 8.0);
+
+        let json = graph.to_json(true).unwrap();
+        let restored = CompGraph::from_json(&json, &registry).unwrap();
+        assert_eq!(restored.cache(result), Some(6.0));
+    }
+
+    #[test]
+    fn test_errors() {
+        let (mut graph, _) = sample_graph();
+        let json = graph.to_json(false).unwrap();
+        assert!(matches!(
+            CompGraph::<f64>::from_json(&json, &OpRegistry::with_builtins()),
+            Err(JsonError::UnknownOp(op)) if op == "double"
+        ));
+
+        let anonymous = graph.add_node([], |_| 1.0);
+        assert!(matches!(
+            graph.to_json(false),
+            Err(JsonError::AnonymousOp(node)) if node.0 == anonymous.0
+        ));
+
+        let json = r#"{"version":1,"nodes":[{"kind":"op","op":"neg","inputs":[0]}]}"#;
+        assert!(matches!(
+            CompGraph::<f64>::from_json(json, &OpRegistry::with_builtins()),
+            Err(JsonError::InvalidInput { node: 0, input: 0 })
+        ));
+
+        let mut graph = CompGraph::<f64>::new();
+        graph.add_input_node("x");
+        graph.add_input_node("x");
+        assert!(matches!(
+            CompGraph::<f64>::from_json(&graph.to_json(false).unwrap(), &OpRegistry::new()),
+            Err(JsonError::DuplicateInput(name)) if name == "x"
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "add is a name of a builtin operation")]
+    fn test_builtin_name() {
+        OpRegistry::<f64>::new().register("add", double);
+    }
+}
@@ -0,0 +1,200 @@
+use super::{registry, CompGraph, NodeId, NodeOp, OpKind};
+use smallvec::{smallvec, SmallVec};
+use std::borrow::Cow;
+
+impl<T: Clone + 'static> CompGraph<T> {
+    /// Adds node whose operation produces `outputs` values at once,
+    /// returns ids of [`OpKind::Port`] nodes holding each of the values
+    /// to be used as inputs of other nodes.
+    ///
+    /// The operation is run once for all outputs, when any of them is requested
+    /// after the inputs changed, and dependents of an output are invalidated with it.
+    pub fn add_multi_output_node(
+        &mut self,
+        inputs: impl IntoIterator<Item = NodeId>,
+        outputs: usize,
+        op: impl 'static + FnMut(&mut dyn Iterator<Item = T>) -> Vec<T>,
+    ) -> Vec<NodeId> {
+        let node = self.push_multi_output(
+            inputs.into_iter().collect(),
+            OpKind::Custom,
+            outputs,
+            Box::new(op),
+        );
+        self.add_ports(node, outputs)
+    }
+
+    /// Same as [`add_multi_output_node`](Self::add_multi_output_node) but remembers `name`
+    /// of the operation so the graph can be serialized and restored with an
+    /// [`OpRegistry`](super::OpRegistry) where it is registered with
+    /// [`register_multi`](super::OpRegistry::register_multi).
+    ///
+    /// Panics if `name` is a name of a [`BuiltinOp`](crate::ops::BuiltinOp).
+    pub fn add_named_multi_output_node(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        inputs: impl IntoIterator<Item = NodeId>,
+        outputs: usize,
+        op: impl 'static + FnMut(&mut dyn Iterator<Item = T>) -> Vec<T>,
+    ) -> Vec<NodeId> {
+        let name = name.into();
+        registry::assert_not_builtin(&name);
+        let node = self.push_multi_output(
+            inputs.into_iter().collect(),
+            OpKind::Named(name),
+            outputs,
+            Box::new(op),
+        );
+        self.add_ports(node, outputs)
+    }
+
+    fn push_multi_output(
+        &mut self,
+        node_inputs: SmallVec<[NodeId; 2]>,
+        kind: OpKind,
+        count: usize,
+        op: super::BoxedMultiOp<T>,
+    ) -> NodeId {
+        assert!(count > 0, "node should have at least one output");
+        self.push_node(
+            node_inputs,
+            kind,
+            NodeOp::Multi {
+                op,
+                count,
+                outputs: Vec::new(),
+            },
+        )
+    }
+
+    fn add_ports(&mut self, node: NodeId, outputs: usize) -> Vec<NodeId> {
+        (0..outputs)
+            .map(|index| self.push_node(smallvec![node], OpKind::Port(index), NodeOp::None))
+            .collect()
+    }
+}
+
+impl<T> CompGraph<T> {
+    /// Number of values the operation of `node` produces, which are read by its ports
+    pub(super) fn output_count(&self, node: NodeId) -> usize {
+        match self.nodes[node.0].op {
+            NodeOp::Multi { count, .. } => count,
+            _ => 1,
+        }
+    }
+
+    /// Finds the first port of a loaded graph whose input doesn't have its output
+    pub(super) fn invalid_port(&self) -> Option<(NodeId, NodeId)> {
+        self.nodes.iter().enumerate().find_map(|(id, node)| {
+            let OpKind::Port(index) = node.kind else {
+                return None;
+            };
+            let input = node.node_inputs[0];
+            let is_valid = matches!(
+                self.nodes[input.0].op,
+                NodeOp::Multi { count, .. } if index < count
+            );
+            (!is_valid).then_some((NodeId(id), input))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comp_graph3::{JsonError, OpRegistry, RewireError};
+    use crate::ops::BuiltinOp;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_multi_output() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let y = graph.add_input_node("y");
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let outputs = graph.add_multi_output_node([x], 2, move |args| {
+            counter.set(counter.get() + 1);
+            let x: f64 = args.next().unwrap();
+            vec![x.sin(), x.cos()]
+        });
+        let [sin, cos] = outputs[..] else {
+            panic!("two outputs expected")
+        };
+        let scaled = graph.add_op(BuiltinOp::Mul, [cos, y]);
+        let result = graph.add_op(BuiltinOp::Add, [sin, scaled]);
+        graph.set_input("x", 1.0);
+        graph.set_input("y", 2.0);
+
+        assert_eq!(graph.compute(result), 1f64.sin() + 1f64.cos() * 2.0);
+        assert_eq!(calls.get(), 1);
+
+        graph.set_input("y", 3.0);
+        assert_eq!(graph.cache(sin), Some(1f64.sin()));
+        assert_eq!(graph.compute(result), 1f64.sin() + 1f64.cos() * 3.0);
+        assert_eq!(calls.get(), 1);
+
+        graph.set_input("x", 2.0);
+        assert_eq!(graph.cache(sin), None);
+        assert_eq!(graph.cache(cos), None);
+        assert_eq!(graph.compute(cos), 2f64.cos());
+        assert_eq!(graph.compute(sin), 2f64.sin());
+        assert_eq!(calls.get(), 2);
+    }
+
+    fn polar(args: &mut dyn Iterator<Item = f64>) -> Vec<f64> {
+        let (x, y) = (args.next().unwrap(), args.next().unwrap());
+        vec![x.hypot(y), y.atan2(x)]
+    }
+
+    #[test]
+    fn test_ports() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let y = graph.add_input_node("y");
+        let ports = graph.add_named_multi_output_node("polar", [x, y], 2, polar);
+        let [radius, angle] = ports[..] else {
+            panic!("two outputs expected")
+        };
+        let result = graph.add_op(BuiltinOp::Mul, [radius, angle]);
+        graph.set_input("x", 0.0);
+        graph.set_input("y", 2.0);
+        assert_eq!(graph.compute(result), 2.0 * std::f64::consts::FRAC_PI_2);
+
+        let node = graph.node(angle);
+        assert_eq!(node.kind(), &OpKind::Port(1));
+        let primary = node.inputs()[0];
+        assert_eq!(graph.node(primary).output_count(), 2);
+        assert_eq!(graph.node(result).output_count(), 1);
+        assert_eq!(graph.node(angle).to_string(), "output1(polar(x, y))");
+        assert_eq!(
+            graph.set_node_inputs(angle, [x]),
+            Err(RewireError::MultiOutput(angle))
+        );
+        assert_eq!(
+            graph.replace_op(primary, |_| 0.0),
+            Err(RewireError::MultiOutput(primary))
+        );
+
+        let mut registry = OpRegistry::with_builtins();
+        registry.register_multi("polar", 2, polar);
+        for mut restored in [
+            CompGraph::from_json(&graph.to_json(true).unwrap(), &registry).unwrap(),
+            CompGraph::from_binary(&graph.to_binary().unwrap(), &registry).unwrap(),
+        ] {
+            assert_eq!(restored.compute(angle), std::f64::consts::FRAC_PI_2);
+            restored.set_input("x", 2.0);
+            assert_eq!(restored.compute(radius), 8f64.sqrt());
+        }
+
+        let json = graph
+            .to_json(false)
+            .unwrap()
+            .replace(r#""index":1"#, r#""index":2"#);
+        assert!(matches!(
+            CompGraph::from_json(&json, &registry),
+            Err(JsonError::InvalidInput { node, input }) if node == angle.0 && input == primary.0
+        ));
+    }
+}
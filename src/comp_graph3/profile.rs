@@ -0,0 +1,235 @@
+use super::{CompGraph, NodeId, OpKind};
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+/// Statistics of a node collected while profiling
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeProfile {
+    pub node: NodeId,
+    /// Name of the input or the operation, if it has one
+    pub name: Option<String>,
+    /// How many times the value of the node was requested
+    pub calls: u64,
+    pub cache_hits: u64,
+    pub cache_misses: u64,
+    /// Wall time spent computing the node including computing of its inputs
+    pub total_time: Duration,
+    /// Wall time spent in the operation of the node itself
+    pub self_time: Duration,
+}
+
+/// Collected profile of [`CompGraph::compute`] calls
+#[derive(Clone, Debug)]
+pub struct Profile {
+    started: Instant,
+    // indexed by node id, `None` for nodes that were never requested
+    nodes: Vec<Option<NodeProfile>>,
+    spans: Vec<Span>,
+}
+
+/// Single computation of a node
+#[derive(Copy, Clone, Debug)]
+struct Span {
+    node: usize,
+    start: Duration,
+    duration: Duration,
+}
+
+/// Records [`Profile`] during evaluation
+pub(super) struct Profiler {
+    profile: Profile,
+    // time spent in inputs of each node being computed
+    inputs_time: Vec<Duration>,
+}
+
+impl Profile {
+    /// Statistics of all requested nodes, the most expensive ones by `self_time` first
+    pub fn report(&self) -> Vec<NodeProfile> {
+        let mut report = self.nodes.iter().flatten().cloned().collect::<Vec<_>>();
+        report.sort_by(|a, b| b.self_time.cmp(&a.self_time).then(a.node.0.cmp(&b.node.0)));
+        report
+    }
+
+    /// Statistics of `node` if it was requested
+    pub fn node(&self, node: NodeId) -> Option<&NodeProfile> {
+        self.nodes.get(node.0)?.as_ref()
+    }
+
+    /// Exports every computation of a node as a complete event of Chrome trace format,
+    /// which can be opened in `chrome://tracing` or Perfetto
+    pub fn to_chrome_trace(&self) -> String {
+        let events = self
+            .spans
+            .iter()
+            .map(|span| {
+                let name = self.nodes[span.node]
+                    .as_ref()
+                    .and_then(|node| node.name.clone())
+                    .unwrap_or_else(|| format!("node {}", span.node));
+                json!({
+                    "name": name,
+                    "cat": "node",
+                    "ph": "X",
+                    "ts": span.start.as_secs_f64() * 1e6,
+                    "dur": span.duration.as_secs_f64() * 1e6,
+                    "pid": 1,
+                    "tid": 1,
+                    "args": { "node": span.node },
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({ "traceEvents": events }).to_string()
+    }
+}
+
+impl Profiler {
+    fn new() -> Self {
+        Self {
+            profile: Profile {
+                started: Instant::now(),
+                nodes: vec![],
+                spans: vec![],
+            },
+            inputs_time: vec![],
+        }
+    }
+
+    fn stats(&mut self, node: usize, kind: &OpKind) -> &mut NodeProfile {
+        if self.profile.nodes.len() <= node {
+            self.profile.nodes.resize(node + 1, None);
+        }
+        self.profile.nodes[node].get_or_insert_with(|| NodeProfile {
+            node: NodeId(node),
+            name: kind.name().map(str::to_owned),
+            calls: 0,
+            cache_hits: 0,
+            cache_misses: 0,
+            total_time: Duration::ZERO,
+            self_time: Duration::ZERO,
+        })
+    }
+
+    /// Called before computing a node, returns time it started
+    pub(super) fn enter(&mut self) -> Instant {
+        self.inputs_time.push(Duration::ZERO);
+        Instant::now()
+    }
+
+    /// Called after computing `node` started by [`enter`](Self::enter)
+    pub(super) fn computed(&mut self, node: usize, kind: &OpKind, started: Instant) {
+        let duration = started.elapsed();
+        let inputs_time = self
+            .inputs_time
+            .pop()
+            .expect("enter should have been called");
+        if let Some(parent) = self.inputs_time.last_mut() {
+            *parent += duration;
+        }
+        let start = started - self.profile.started;
+        self.profile.spans.push(Span {
+            node,
+            start,
+            duration,
+        });
+
+        let stats = self.stats(node, kind);
+        stats.calls += 1;
+        stats.cache_misses += 1;
+        stats.total_time += duration;
+        stats.self_time += duration.saturating_sub(inputs_time);
+    }
+
+    pub(super) fn cache_hit(&mut self, node: usize, kind: &OpKind) {
+        let stats = self.stats(node, kind);
+        stats.calls += 1;
+        stats.cache_hits += 1;
+    }
+}
+
+impl<T> CompGraph<T> {
+    /// Starts collecting a new [`Profile`] of [`compute`](Self::compute) calls,
+    /// discarding the current one
+    pub fn start_profiling(&mut self) {
+        self.observers.profiler = Some(Profiler::new());
+    }
+
+    /// Stops profiling and returns the collected profile
+    pub fn stop_profiling(&mut self) -> Option<Profile> {
+        self.observers
+            .profiler
+            .take()
+            .map(|profiler| profiler.profile)
+    }
+
+    /// Profile collected so far, if profiling is enabled
+    pub fn profile(&self) -> Option<&Profile> {
+        self.observers
+            .profiler
+            .as_ref()
+            .map(|profiler| &profiler.profile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::BuiltinOp;
+    use std::thread::sleep;
+
+    #[test]
+    fn test_profile() {
+        let mut graph = CompGraph::new();
+        let x1 = graph.add_input_node("x1");
+        let slow_node = graph.add_named_node("slow", [x1], |x| {
+            sleep(Duration::from_millis(20));
+            x.next().unwrap()
+        });
+        let result = graph.add_op(BuiltinOp::Neg, [slow_node]);
+        graph.set_input("x1", 1.0f32);
+
+        graph.start_profiling();
+        graph.compute(result);
+        graph.compute(result);
+        let profile = graph.stop_profiling().unwrap();
+        assert!(graph.profile().is_none());
+
+        let report = profile.report();
+        assert_eq!(report.len(), 3);
+        let slow = &report[0];
+        assert_eq!(slow.node, slow_node);
+        assert_eq!(slow.name.as_deref(), Some("slow"));
+        assert_eq!((slow.calls, slow.cache_hits, slow.cache_misses), (1, 0, 1));
+        assert!(slow.self_time >= Duration::from_millis(20));
+
+        let result = profile.node(result).unwrap();
+        assert_eq!(
+            (result.calls, result.cache_hits, result.cache_misses),
+            (2, 1, 1)
+        );
+        assert!(result.total_time >= slow.total_time);
+        assert!(result.self_time < slow.self_time);
+        assert_eq!(profile.node(x1).unwrap().cache_hits, 1);
+    }
+
+    #[test]
+    fn test_chrome_trace() {
+        let mut graph = CompGraph::new();
+        let x1 = graph.add_input_node("x1");
+        let node = graph.add_node([x1], |x| x.next().unwrap());
+        let result = graph.add_op(BuiltinOp::Neg, [node]);
+        graph.set_input("x1", 1.0f32);
+        graph.start_profiling();
+        graph.compute(result);
+
+        let trace: serde_json::Value =
+            serde_json::from_str(&graph.profile().unwrap().to_chrome_trace()).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+        let names = events
+            .iter()
+            .map(|event| event["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["node 1", "neg"]);
+        assert!(events.iter().all(|event| event["ph"] == "X"));
+        assert_eq!(events[1]["args"]["node"], result.0);
+    }
+}
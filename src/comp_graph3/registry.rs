@@ -0,0 +1,110 @@
+use super::{builtin_op, NodeOp, OpKind};
+use crate::ops::{BuiltinOp, Scalar};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// Maps operation names to their implementations,
+/// so operations of a deserialized graph can be restored
+pub struct OpRegistry<T> {
+    ops: HashMap<Cow<'static, str>, RegisteredOp<T>>,
+}
+
+struct RegisteredOp<T> {
+    kind: OpKind,
+    factory: Box<dyn Fn() -> NodeOp<T>>,
+}
+
+impl<T: 'static> OpRegistry<T> {
+    /// Creates empty registry
+    pub fn new() -> Self {
+        Self {
+            ops: HashMap::new(),
+        }
+    }
+
+    /// Registers `op` under `name`, every restored node gets its own clone of `op`.
+    ///
+    /// Nodes that should be restored with it must be added with
+    /// [`CompGraph::add_named_node`](super::CompGraph::add_named_node) using the same `name`,
+    /// which can't be a name of a [`BuiltinOp`], as they are saved the same way.
+    pub fn register(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        op: impl 'static + Clone + FnMut(&mut dyn Iterator<Item = T>) -> T,
+    ) -> &mut Self {
+        let name = name.into();
+        assert_not_builtin(&name);
+        self.ops.insert(
+            name.clone(),
+            RegisteredOp {
+                kind: OpKind::Named(name),
+                factory: Box::new(move || NodeOp::Single(Box::new(op.clone()))),
+            },
+        );
+        self
+    }
+
+    /// Same as [`register`](Self::register) for an operation producing `outputs` values, added with
+    /// [`CompGraph::add_named_multi_output_node`](super::CompGraph::add_named_multi_output_node)
+    pub fn register_multi(
+        &mut self,
+        name: impl Into<Cow<'static, str>>,
+        outputs: usize,
+        op: impl 'static + Clone + FnMut(&mut dyn Iterator<Item = T>) -> Vec<T>,
+    ) -> &mut Self {
+        let name = name.into();
+        assert_not_builtin(&name);
+        assert!(outputs > 0, "node should have at least one output");
+        self.ops.insert(
+            name.clone(),
+            RegisteredOp {
+                kind: OpKind::Named(name),
+                factory: Box::new(move || NodeOp::Multi {
+                    op: Box::new(op.clone()),
+                    count: outputs,
+                    outputs: Vec::new(),
+                }),
+            },
+        );
+        self
+    }
+
+    /// Creates new instance of operation registered under `name`
+    pub(super) fn instantiate(&self, name: &str) -> Option<(OpKind, NodeOp<T>)> {
+        self.ops
+            .get(name)
+            .map(|op| (op.kind.clone(), (op.factory)()))
+    }
+}
+
+/// Panics if `name` of a named operation is a name of a builtin one
+pub(super) fn assert_not_builtin(name: &str) {
+    assert!(
+        BuiltinOp::from_name(name).is_none(),
+        "{} is a name of a builtin operation",
+        name
+    );
+}
+
+impl<T: Scalar> OpRegistry<T> {
+    /// Creates registry that already contains all [`BuiltinOp`]s under their names
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        for op in BuiltinOp::ALL {
+            registry.ops.insert(
+                op.name().into(),
+                RegisteredOp {
+                    kind: OpKind::Builtin(op),
+                    factory: Box::new(move || NodeOp::Single(builtin_op(op))),
+                },
+            );
+        }
+        registry
+    }
+}
+
+impl<T: 'static> Default for OpRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,380 @@
+use super::{builtin_op, registry, CompGraph, NodeId, NodeOp, OpKind};
+use crate::ops::{BuiltinOp, Scalar};
+use smallvec::SmallVec;
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+
+/// Error of changing an existing node
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RewireError {
+    /// `node` would depend on itself through `input`
+    Cycle { node: NodeId, input: NodeId },
+    /// Input and constant nodes get values only from [`CompGraph::set_input`]
+    /// and [`CompGraph::set_constant`]
+    InputNode(NodeId),
+    /// Builtin operation of the node expects a different number of inputs
+    WrongArity { node: NodeId, expected: usize },
+    /// Node is a port, which always reads the node it was created for,
+    /// or its operation produces several outputs
+    MultiOutput(NodeId),
+    /// Node was not added by [`CompGraph::call`] or it was already inlined
+    NotCall(NodeId),
+    /// Node is not in the graph
+    UnknownNode(NodeId),
+}
+
+impl Display for RewireError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RewireError::Cycle { node, input } => write!(
+                f,
+                "node {} can't take node {} as input as it would create a cycle",
+                node.0, input.0
+            ),
+            RewireError::InputNode(node) => {
+                write!(
+                    f,
+                    "node {} is an input or constant and can't be changed",
+                    node.0
+                )
+            }
+            RewireError::WrongArity { node, expected } => {
+                write!(f, "node {} expects {} inputs", node.0, expected)
+            }
+            RewireError::MultiOutput(node) => write!(
+                f,
+                "node {} is a port or has several outputs and can't be changed",
+                node.0
+            ),
+            RewireError::NotCall(node) => write!(f, "node {} is not a subgraph call", node.0),
+            RewireError::UnknownNode(node) => write!(f, "node {} is not in the graph", node.0),
+        }
+    }
+}
+
+impl std::error::Error for RewireError {}
+
+impl<T> CompGraph<T> {
+    /// Replaces inputs of `node`, updating dependencies and invalidating caches of it and its dependents.
+    ///
+    /// Inputs can be any nodes of the graph, also added after `node`, as long as they don't depend on it.
+    pub fn set_node_inputs(
+        &mut self,
+        node: NodeId,
+        inputs: impl IntoIterator<Item = NodeId>,
+    ) -> Result<(), RewireError> {
+        let node_inputs = inputs.into_iter().collect::<SmallVec<_>>();
+        if let Some(&unknown) = [node]
+            .iter()
+            .chain(&node_inputs)
+            .find(|id| id.0 >= self.nodes.len())
+        {
+            return Err(RewireError::UnknownNode(unknown));
+        }
+        let current = &self.nodes[node.0];
+        match current.kind {
+            OpKind::Input(_) | OpKind::Constant(_) => return Err(RewireError::InputNode(node)),
+            OpKind::Port(_) => return Err(RewireError::MultiOutput(node)),
+            OpKind::Builtin(op) if op.arity() != node_inputs.len() => {
+                return Err(RewireError::WrongArity {
+                    node,
+                    expected: op.arity(),
+                })
+            }
+            _ => {}
+        }
+        if let Some(&input) = node_inputs
+            .iter()
+            .find(|&&input| self.depends_on(input, node))
+        {
+            return Err(RewireError::Cycle { node, input });
+        }
+
+        self.replace_inputs(node, node_inputs);
+        Ok(())
+    }
+
+    /// Replaces operation of `node` keeping its inputs, invalidating caches of it and its dependents
+    pub fn replace_op(
+        &mut self,
+        node: NodeId,
+        op: impl 'static + FnMut(&mut dyn Iterator<Item = T>) -> T,
+    ) -> Result<(), RewireError> {
+        self.check_replaceable(node)?;
+        self.set_op(node, OpKind::Custom, NodeOp::Single(Box::new(op)));
+        Ok(())
+    }
+
+    /// Same as [`replace_op`](Self::replace_op) but remembers `name` of the operation
+    /// like [`add_named_node`](Self::add_named_node), so the graph can still be serialized.
+    ///
+    /// Panics if `name` is a name of a [`BuiltinOp`].
+    pub fn replace_named_op(
+        &mut self,
+        node: NodeId,
+        name: impl Into<Cow<'static, str>>,
+        op: impl 'static + FnMut(&mut dyn Iterator<Item = T>) -> T,
+    ) -> Result<(), RewireError> {
+        let name = name.into();
+        registry::assert_not_builtin(&name);
+        self.check_replaceable(node)?;
+        self.set_op(node, OpKind::Named(name), NodeOp::Single(Box::new(op)));
+        Ok(())
+    }
+
+    /// Checks that operation of `node` can be replaced by one with a single output
+    fn check_replaceable(&self, node: NodeId) -> Result<(), RewireError> {
+        let current = self
+            .nodes
+            .get(node.0)
+            .ok_or(RewireError::UnknownNode(node))?;
+        match (&current.kind, &current.op) {
+            (OpKind::Input(_) | OpKind::Constant(_), _) => Err(RewireError::InputNode(node)),
+            (OpKind::Port(_), _) | (_, NodeOp::Multi { .. }) => Err(RewireError::MultiOutput(node)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Replaces operation of `node`, invalidating caches of it and its dependents
+    fn set_op(&mut self, node: NodeId, kind: OpKind, op: NodeOp<T>) {
+        // restores edges a select may have dropped for the branch it didn't choose
+        let inputs = self.nodes[node.0].node_inputs.clone();
+        self.replace_inputs(node, inputs);
+        let current = &mut self.nodes[node.0];
+        current.kind = kind;
+        current.op = op;
+    }
+
+    /// Replaces inputs of `node` without any checks, invalidating it and its dependents
+    pub(super) fn replace_inputs(&mut self, node: NodeId, node_inputs: SmallVec<[NodeId; 2]>) {
+        self.invalidate_node(node);
+        for input in std::mem::take(&mut self.nodes[node.0].node_inputs) {
+            self.nodes[input.0]
+                .dependents
+                .retain(|dependent| *dependent != node.0);
+        }
+        for input in node_inputs.iter() {
+            let dependents = &mut self.nodes[input.0].dependents;
+            if !dependents.contains(&node.0) {
+                dependents.push(node.0);
+            }
+        }
+        self.nodes[node.0].node_inputs = node_inputs;
+    }
+
+    /// Sets inputs of loaded nodes that have none yet, keeping caches.
+    ///
+    /// Returns a node with an input that doesn't exist or is on a cycle.
+    pub(super) fn attach_inputs(
+        &mut self,
+        wiring: Vec<(NodeId, SmallVec<[NodeId; 2]>)>,
+    ) -> Result<(), (NodeId, NodeId)> {
+        for (node, node_inputs) in wiring {
+            if let Some(&input) = node_inputs.iter().find(|input| input.0 >= self.nodes.len()) {
+                return Err((node, input));
+            }
+            for input in node_inputs.iter() {
+                let dependents = &mut self.nodes[input.0].dependents;
+                // the same input can be passed several times
+                if dependents.last() != Some(&node.0) {
+                    dependents.push(node.0);
+                }
+            }
+            self.nodes[node.0].node_inputs = node_inputs;
+        }
+
+        // Kahn's algorithm, nodes left with unvisited inputs depend on a cycle
+        let mut remaining = vec![0; self.nodes.len()];
+        for node in &self.nodes {
+            for &dependent in &node.dependents {
+                remaining[dependent] += 1;
+            }
+        }
+        let mut ready = (0..self.nodes.len())
+            .filter(|&id| remaining[id] == 0)
+            .collect::<Vec<_>>();
+        while let Some(next) = ready.pop() {
+            for &dependent in &self.nodes[next].dependents {
+                remaining[dependent] -= 1;
+                if remaining[dependent] == 0 {
+                    ready.push(dependent);
+                }
+            }
+        }
+        let Some(start) = (0..self.nodes.len()).find(|&id| remaining[id] > 0) else {
+            return Ok(());
+        };
+        // following inputs that are left leads to a node on the cycle
+        let mut visited = vec![false; self.nodes.len()];
+        let mut node = start;
+        loop {
+            visited[node] = true;
+            let input = self.nodes[node]
+                .node_inputs
+                .iter()
+                .find(|input| remaining[input.0] > 0)
+                .expect("node left by Kahn's algorithm should have an input left");
+            if visited[input.0] {
+                return Err((NodeId(node), *input));
+            }
+            node = input.0;
+        }
+    }
+
+    /// Whether `node` is `target` or depends on it
+    pub(super) fn depends_on(&self, node: NodeId, target: NodeId) -> bool {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut stack = vec![node.0];
+        while let Some(next) = stack.pop() {
+            if next == target.0 {
+                return true;
+            }
+            if !std::mem::replace(&mut visited[next], true) {
+                stack.extend(self.nodes[next].node_inputs.iter().map(|input| input.0));
+            }
+        }
+        false
+    }
+}
+
+impl<T: Scalar> CompGraph<T> {
+    /// Replaces operation of `node` with builtin `op` keeping its inputs,
+    /// invalidating caches of it and its dependents
+    pub fn replace_with_builtin(&mut self, node: NodeId, op: BuiltinOp) -> Result<(), RewireError> {
+        self.check_replaceable(node)?;
+        if self.nodes[node.0].node_inputs.len() != op.arity() {
+            return Err(RewireError::WrongArity {
+                node,
+                expected: op.arity(),
+            });
+        }
+        self.set_op(node, OpKind::Builtin(op), NodeOp::Single(builtin_op(op)));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::comp_graph3::OpRegistry;
+    use crate::ops::BuiltinOp;
+
+    #[test]
+    fn test_set_node_inputs() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let y = graph.add_input_node("y");
+        let neg = graph.add_op(BuiltinOp::Neg, [x]);
+        let result = graph.add_op(BuiltinOp::Add, [neg, x]);
+        graph.set_input("x", 1.0);
+        graph.set_input("y", 10.0);
+        assert_eq!(graph.compute(result), 0.0);
+
+        // input added after the node
+        let double = graph.add_op(BuiltinOp::Add, [y, y]);
+        graph.set_node_inputs(neg, [double]).unwrap();
+        assert_eq!(graph.cache(result), None);
+        assert_eq!(graph.compute(result), -19.0);
+        graph.set_input("x", 2.0);
+        assert_eq!(graph.cache(neg), Some(-20.0));
+        assert_eq!(graph.compute(result), -18.0);
+
+        assert_eq!(
+            graph.set_node_inputs(double, [result, y]),
+            Err(RewireError::Cycle {
+                node: double,
+                input: result
+            })
+        );
+        assert_eq!(
+            graph.set_node_inputs(neg, [x, y]),
+            Err(RewireError::WrongArity {
+                node: neg,
+                expected: 1
+            })
+        );
+        assert_eq!(
+            graph.set_node_inputs(x, [y]),
+            Err(RewireError::InputNode(x))
+        );
+        assert_eq!(
+            graph.set_node_inputs(neg, [NodeId(100)]),
+            Err(RewireError::UnknownNode(NodeId(100)))
+        );
+        assert_eq!(
+            graph.set_node_inputs(NodeId(100), [x]),
+            Err(RewireError::UnknownNode(NodeId(100)))
+        );
+        assert_eq!(graph.compute(result), -18.0);
+    }
+
+    #[test]
+    fn test_replace_op() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let node = graph.add_op(BuiltinOp::Neg, [x]);
+        let result = graph.add_op(BuiltinOp::Mul, [node, x]);
+        graph.set_input("x", 3.0f32);
+        assert_eq!(graph.compute(result), -9.0);
+
+        graph
+            .replace_op(node, |args| args.next().unwrap() + 1.0)
+            .unwrap();
+        assert_eq!(graph.op_kind(node), &OpKind::Custom);
+        assert_eq!(graph.compute(result), 12.0);
+        assert_eq!(graph.replace_op(x, |_| 0.0), Err(RewireError::InputNode(x)));
+
+        graph.replace_with_builtin(node, BuiltinOp::Sin).unwrap();
+        assert_eq!(graph.op_kind(node), &OpKind::Builtin(BuiltinOp::Sin));
+        assert_eq!(graph.compute(result), 3f32.sin() * 3.0);
+        assert_eq!(graph.compile(result).unwrap().run(&[2.0]), 2f32.sin() * 2.0);
+        assert_eq!(
+            graph.replace_with_builtin(node, BuiltinOp::Add),
+            Err(RewireError::WrongArity { node, expected: 2 })
+        );
+        assert_eq!(
+            graph.replace_with_builtin(NodeId(100), BuiltinOp::Neg),
+            Err(RewireError::UnknownNode(NodeId(100)))
+        );
+    }
+
+    #[test]
+    fn test_replace_named_op() {
+        fn inc(args: &mut dyn Iterator<Item = f64>) -> f64 {
+            args.next().unwrap() + 1.0
+        }
+
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let node = graph.add_op(BuiltinOp::Neg, [x]);
+        graph.replace_named_op(node, "inc", inc).unwrap();
+        assert_eq!(graph.op_kind(node), &OpKind::Named("inc".into()));
+        graph.set_input("x", 1.0);
+        assert_eq!(graph.compute(node), 2.0);
+
+        let mut registry = OpRegistry::with_builtins();
+        registry.register("inc", inc);
+        let mut restored = CompGraph::from_json(&graph.to_json(false).unwrap(), &registry).unwrap();
+        assert_eq!(restored.compute(node), 2.0);
+    }
+
+    #[test]
+    fn test_serialize_forward_inputs() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let neg = graph.add_op(BuiltinOp::Neg, [x]);
+        let double = graph.add_op(BuiltinOp::Add, [x, x]);
+        graph.set_node_inputs(neg, [double]).unwrap();
+        graph.set_input("x", 2.0);
+
+        let registry = OpRegistry::with_builtins();
+        let mut restored =
+            CompGraph::<f64>::from_json(&graph.to_json(false).unwrap(), &registry).unwrap();
+        assert_eq!(restored.compute(neg), -4.0);
+        let mut restored =
+            CompGraph::<f64>::from_binary(&graph.to_binary().unwrap(), &registry).unwrap();
+        assert_eq!(restored.compute(neg), -4.0);
+        // repeated input of `double` is a single edge
+        assert_eq!(restored.nodes[x.0].dependents.len(), 1);
+    }
+}
@@ -0,0 +1,199 @@
+use super::{CompGraph, NodeId, NodeOp, OpKind};
+use crate::ops::Scalar;
+use smallvec::SmallVec;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+type UpdateFn<T> = Box<dyn FnMut(&T, T) -> T>;
+
+/// State of stateful nodes of a graph, advanced by [`CompGraph::step`]
+pub(super) struct Simulation<T> {
+    nodes: Vec<StatefulNode<T>>,
+    time: u64,
+}
+
+impl<T> Default for Simulation<T> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            time: 0,
+        }
+    }
+}
+
+struct StatefulNode<T> {
+    node: NodeId,
+    // node whose value updates the state, set by `connect`
+    source: Option<NodeId>,
+    initial: T,
+    // shared with the operation of the node, which returns it
+    state: Rc<RefCell<T>>,
+    update: UpdateFn<T>,
+}
+
+impl<T: Clone + 'static> CompGraph<T> {
+    /// Adds node whose value is the value of another node in the previous step,
+    /// `initial` before the first step.
+    ///
+    /// The node has no inputs until the next [`step`](Self::step), so it can be used
+    /// to create feedback loops. The other node must be set with [`connect`](Self::connect).
+    pub fn add_delay_node(&mut self, initial: T) -> NodeId {
+        self.add_stateful_node(initial, |_, value| value)
+    }
+
+    /// Adds node whose value is `initial` updated by `update` with the value of
+    /// the node connected with [`connect`](Self::connect) at every [`step`](Self::step)
+    pub fn add_stateful_node(
+        &mut self,
+        initial: T,
+        update: impl 'static + FnMut(&T, T) -> T,
+    ) -> NodeId {
+        let state = Rc::new(RefCell::new(initial.clone()));
+        let value = state.clone();
+        let node = self.push_node(
+            SmallVec::new(),
+            OpKind::Custom,
+            NodeOp::Single(Box::new(move |_| value.borrow().clone())),
+        );
+        self.simulation.nodes.push(StatefulNode {
+            node,
+            source: None,
+            initial,
+            state,
+            update: Box::new(update),
+        });
+        node
+    }
+
+    /// Sets node whose value updates state of stateful `node` at every step
+    pub fn connect(&mut self, node: NodeId, source: NodeId) {
+        self.stateful_node(node).source = Some(source);
+    }
+
+    /// Advances simulated time by one step, updating states of all stateful nodes.
+    ///
+    /// All states are updated with values of their sources computed before the step,
+    /// so the order of stateful nodes doesn't matter.
+    pub fn step(&mut self) {
+        let sources = self
+            .simulation
+            .nodes
+            .iter()
+            .map(|stateful| stateful.source.expect("stateful node is not connected"))
+            .collect::<Vec<_>>();
+        let values = sources
+            .into_iter()
+            .map(|source| self.compute(source))
+            .collect::<Vec<_>>();
+
+        let mut updated = Vec::with_capacity(values.len());
+        for (stateful, value) in self.simulation.nodes.iter_mut().zip(values) {
+            let next = (stateful.update)(&stateful.state.borrow(), value);
+            *stateful.state.borrow_mut() = next;
+            updated.push(stateful.node);
+        }
+        for node in updated {
+            self.invalidate_node(node);
+        }
+        self.simulation.time += 1;
+    }
+
+    /// Number of steps since the graph was created or its state reset
+    pub fn time(&self) -> u64 {
+        self.simulation.time
+    }
+
+    /// Sets states of all stateful nodes to their initial values and time to 0,
+    /// so the simulation can be repeated
+    pub fn reset_state(&mut self) {
+        let nodes = self
+            .simulation
+            .nodes
+            .iter()
+            .map(|stateful| {
+                *stateful.state.borrow_mut() = stateful.initial.clone();
+                stateful.node
+            })
+            .collect::<Vec<_>>();
+        for node in nodes {
+            self.invalidate_node(node);
+        }
+        self.simulation.time = 0;
+    }
+
+    fn stateful_node(&mut self, node: NodeId) -> &mut StatefulNode<T> {
+        self.simulation
+            .nodes
+            .iter_mut()
+            .find(|stateful| stateful.node == node)
+            .expect("node is not stateful")
+    }
+}
+
+impl<T: Scalar> CompGraph<T> {
+    /// Adds node whose value is the sum of values of `input` in all previous steps
+    pub fn add_accumulator_node(&mut self, input: NodeId) -> NodeId {
+        let node = self.add_stateful_node(T::ZERO, |sum, value| *sum + value);
+        self.connect(node, input);
+        node
+    }
+
+    /// Adds node integrating `input` over time with forward Euler method,
+    /// where every step takes `dt` and the value starts at `initial`
+    pub fn add_integrator_node(&mut self, input: NodeId, initial: T, dt: T) -> NodeId {
+        let node = self.add_stateful_node(initial, move |integral, value| *integral + value * dt);
+        self.connect(node, input);
+        node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::BuiltinOp;
+
+    #[test]
+    fn test_feedback() {
+        // Fibonacci numbers
+        let mut graph = CompGraph::new();
+        let previous = graph.add_delay_node(0u32);
+        let current = graph.add_delay_node(1);
+        let next = graph.add_node([previous, current], |args| args.sum());
+        graph.connect(previous, current);
+        graph.connect(current, next);
+
+        let mut values = vec![];
+        for _ in 0..6 {
+            values.push(graph.compute(current));
+            graph.step();
+        }
+        assert_eq!(values, [1, 1, 2, 3, 5, 8]);
+        assert_eq!(graph.time(), 6);
+
+        graph.reset_state();
+        assert_eq!(graph.time(), 0);
+        assert_eq!(graph.compute(next), 1);
+    }
+
+    #[test]
+    fn test_accumulator_and_integrator() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let sum = graph.add_accumulator_node(x);
+        let position = graph.add_integrator_node(x, 10.0, 0.5);
+        let result = graph.add_op(BuiltinOp::Add, [sum, position]);
+        graph.set_input("x", 2.0f64);
+
+        assert_eq!(graph.compute(result), 10.0);
+        graph.step();
+        graph.step();
+        assert_eq!(graph.compute(sum), 4.0);
+        graph.set_input("x", -1.0);
+        graph.step();
+        assert_eq!(graph.compute(sum), 3.0);
+        assert_eq!(graph.compute(position), 11.5);
+
+        graph.reset_state();
+        assert_eq!(graph.compute(result), 10.0);
+    }
+}
@@ -0,0 +1,203 @@
+use super::{CompGraph, NodeId};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Value at a point of time, in units chosen by the user
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Sample<T> {
+    pub time: u64,
+    pub value: T,
+}
+
+/// Iterator of values of output nodes recomputed after every new sample of the inputs,
+/// created by [`CompGraph::stream`]
+pub struct Stream<'g, T> {
+    graph: &'g mut CompGraph<T>,
+    outputs: Vec<NodeId>,
+    sources: Vec<Source<'g, T>>,
+    // time of the next sample of each source, earliest first
+    queue: BinaryHeap<Reverse<(u64, usize)>>,
+    // sources whose next sample wasn't pulled yet
+    pending: Vec<usize>,
+}
+
+struct Source<'g, T> {
+    input: Cow<'static, str>,
+    samples: Box<dyn Iterator<Item = Sample<T>> + 'g>,
+    next: Option<Sample<T>>,
+    received: bool,
+}
+
+impl<T: Clone> CompGraph<T> {
+    /// Starts streaming values of `outputs`, add sources of inputs with [`Stream::source`]
+    pub fn stream(&mut self, outputs: impl IntoIterator<Item = NodeId>) -> Stream<'_, T> {
+        Stream {
+            graph: self,
+            outputs: outputs.into_iter().collect(),
+            sources: vec![],
+            queue: BinaryHeap::new(),
+            pending: vec![],
+        }
+    }
+}
+
+impl<'g, T: Clone> Stream<'g, T> {
+    /// Feeds input `name` with `samples`, which should be ordered by time.
+    ///
+    /// Any iterator can be used, e.g. a [`Receiver`](std::sync::mpsc::Receiver)
+    /// of a channel, in which case the stream waits for the next sample.
+    /// Samples are pulled only when the stream needs the next time of the source,
+    /// so an output is returned as soon as all its samples were received,
+    /// and samples of one source with the same time are applied one at a time.
+    pub fn source(
+        mut self,
+        name: impl Into<Cow<'static, str>>,
+        samples: impl IntoIterator<Item = Sample<T>> + 'g,
+    ) -> Self {
+        let input = name.into();
+        assert!(
+            self.graph.graph_inputs.contains_key(&input),
+            "no such input"
+        );
+        self.pending.push(self.sources.len());
+        self.sources.push(Source {
+            input,
+            samples: Box::new(samples.into_iter()),
+            next: None,
+            received: false,
+        });
+        self
+    }
+
+    /// Sets all inputs to their samples at the earliest time in the queue, returns that time
+    fn apply_next_samples(&mut self) -> Option<u64> {
+        for index in self.pending.drain(..) {
+            let source = &mut self.sources[index];
+            source.next = source.samples.next();
+            if let Some(next) = &source.next {
+                self.queue.push(Reverse((next.time, index)));
+            }
+        }
+        let Reverse((time, _)) = *self.queue.peek()?;
+        while let Some(&Reverse((next_time, index))) = self.queue.peek() {
+            if next_time != time {
+                break;
+            }
+            self.queue.pop();
+            let source = &mut self.sources[index];
+            let sample = source
+                .next
+                .take()
+                .expect("queued source should have sample");
+            source.received = true;
+            self.pending.push(index);
+            // invalidates only nodes depending on this input
+            self.graph.set_input(&source.input, sample.value);
+        }
+        Some(time)
+    }
+}
+
+impl<T: Clone> Iterator for Stream<'_, T> {
+    type Item = Sample<Vec<T>>;
+
+    /// Applies samples with the next time stamp, merged from all sources, and recomputes outputs.
+    ///
+    /// Nothing is returned for times before every source produced its first sample.
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let time = self.apply_next_samples()?;
+            if self.sources.iter().all(|source| source.received) {
+                let value = self
+                    .outputs
+                    .iter()
+                    .map(|&output| self.graph.compute(output))
+                    .collect();
+                return Some(Sample { time, value });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::BuiltinOp;
+    use std::sync::mpsc::channel;
+
+    fn samples(values: &[(u64, f64)]) -> Vec<Sample<f64>> {
+        values
+            .iter()
+            .map(|&(time, value)| Sample { time, value })
+            .collect()
+    }
+
+    #[test]
+    fn test_stream() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let y = graph.add_input_node("y");
+        let neg_x = graph.add_op(BuiltinOp::Neg, [x]);
+        let sum = graph.add_op(BuiltinOp::Add, [neg_x, y]);
+
+        let outputs = graph
+            .stream([neg_x, sum])
+            .source("x", samples(&[(1, 1.0), (3, 2.0), (4, 3.0)]))
+            .source("y", samples(&[(2, 10.0), (4, 20.0), (5, 30.0)]))
+            .collect::<Vec<_>>();
+        let expected = [
+            (2, vec![-1.0, 9.0]),
+            (3, vec![-2.0, 8.0]),
+            (4, vec![-3.0, 17.0]),
+            (5, vec![-3.0, 27.0]),
+        ];
+        let expected = expected
+            .into_iter()
+            .map(|(time, value)| Sample { time, value })
+            .collect::<Vec<_>>();
+        assert_eq!(outputs, expected);
+        // only the changed input invalidated `neg_x` in the last step
+        assert_eq!(graph.node_cache_stats(neg_x).misses, 3);
+    }
+
+    #[test]
+    fn test_channel() {
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let result = graph.add_op(BuiltinOp::Neg, [x]);
+        let (sender, receiver) = channel();
+        for (time, value) in [(1, 1.0f32), (2, 2.0)] {
+            sender.send(Sample { time, value }).unwrap();
+        }
+        drop(sender);
+
+        let outputs = graph
+            .stream([result])
+            .source("x", receiver)
+            .map(|sample| sample.value[0])
+            .collect::<Vec<_>>();
+        assert_eq!(outputs, [-1.0, -2.0]);
+    }
+
+    #[test]
+    fn test_lazy_sources() {
+        use std::cell::Cell;
+
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let pulled = Cell::new(0);
+        let source = samples(&[(1, 1.0), (2, 2.0)])
+            .into_iter()
+            .inspect(|_| pulled.set(pulled.get() + 1));
+
+        let mut stream = graph.stream([x]).source("x", source);
+        assert_eq!(pulled.get(), 0);
+        assert_eq!(stream.next().map(|sample| sample.time), Some(1));
+        // the next sample isn't needed yet
+        assert_eq!(pulled.get(), 1);
+        assert_eq!(stream.next().map(|sample| sample.time), Some(2));
+        assert_eq!(stream.next(), None);
+        assert_eq!(pulled.get(), 2);
+    }
+}
@@ -0,0 +1,321 @@
+use super::fixed_point::report;
+use super::{CompGraph, EvalError, NodeId, OpKind, RewireError};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+type Builder<T> = Rc<dyn Fn(&mut CompGraph<T>, &[NodeId]) -> Vec<NodeId>>;
+
+/// Graph defined as a function with named parameters and outputs,
+/// which can be instantiated many times inside other graphs
+pub struct SubGraph<T> {
+    params: Vec<Cow<'static, str>>,
+    outputs: Vec<Cow<'static, str>>,
+    build: Builder<T>,
+}
+
+impl<T> Clone for SubGraph<T> {
+    fn clone(&self) -> Self {
+        Self {
+            params: self.params.clone(),
+            outputs: self.outputs.clone(),
+            build: self.build.clone(),
+        }
+    }
+}
+
+impl<T> SubGraph<T> {
+    /// Defines subgraph with `params` and `outputs`.
+    ///
+    /// `build` adds nodes of the subgraph to the given graph, using the given nodes
+    /// as values of the parameters, and returns nodes of the outputs in the same order as `outputs`.
+    pub fn new(
+        params: impl IntoIterator<Item = impl Into<Cow<'static, str>>>,
+        outputs: impl IntoIterator<Item = impl Into<Cow<'static, str>>>,
+        build: impl 'static + Fn(&mut CompGraph<T>, &[NodeId]) -> Vec<NodeId>,
+    ) -> Self {
+        Self {
+            params: params.into_iter().map(Into::into).collect(),
+            outputs: outputs.into_iter().map(Into::into).collect(),
+            build: Rc::new(build),
+        }
+    }
+
+    pub fn params(&self) -> impl Iterator<Item = &str> {
+        self.params.iter().map(|name| &**name)
+    }
+
+    pub fn outputs(&self) -> impl Iterator<Item = &str> {
+        self.outputs.iter().map(|name| &**name)
+    }
+
+    /// Position of output `name` in nodes returned by [`CompGraph::call`] and [`CompGraph::inline_call`]
+    pub fn output_index(&self, name: &str) -> Option<usize> {
+        self.outputs.iter().position(|output| output == name)
+    }
+
+    fn build_into(&self, graph: &mut CompGraph<T>, args: &[NodeId]) -> Vec<NodeId> {
+        assert_eq!(
+            args.len(),
+            self.params.len(),
+            "wrong number of arguments for subgraph"
+        );
+        let outputs = (self.build)(graph, args);
+        assert_eq!(
+            outputs.len(),
+            self.outputs.len(),
+            "wrong number of outputs of subgraph"
+        );
+        outputs
+    }
+}
+
+/// Own copy of a [`SubGraph`] evaluated with values of its parameters
+pub(super) struct Instance<T> {
+    graph: CompGraph<T>,
+    params: Vec<(Cow<'static, str>, NodeId)>,
+    outputs: Vec<NodeId>,
+}
+
+impl<T: Clone + PartialEq> Instance<T> {
+    pub(super) fn new(subgraph: &SubGraph<T>) -> Self {
+        let mut graph = CompGraph::new();
+        let params = subgraph
+            .params
+            .iter()
+            .map(|name| (name.clone(), graph.add_input_node(name.clone())))
+            .collect::<Vec<_>>();
+        let ids = params.iter().map(|(_, id)| *id).collect::<Vec<_>>();
+        let outputs = subgraph.build_into(&mut graph, &ids);
+        Self {
+            graph,
+            params,
+            outputs,
+        }
+    }
+
+    pub(super) fn param_count(&self) -> usize {
+        self.params.len()
+    }
+
+    pub(super) fn output_count(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Computes outputs for parameters set to `values`.
+    ///
+    /// If nodes of the instance fail, the errors are reported to `eval_errors`
+    /// as failures of `node` evaluating the instance, like errors of its own.
+    pub(super) fn eval(
+        &mut self,
+        values: impl IntoIterator<Item = T>,
+        node: NodeId,
+        eval_errors: &RefCell<Vec<EvalError>>,
+    ) -> Vec<T> {
+        for ((name, param), value) in self.params.iter().zip(values) {
+            // keeping caches of nodes not depending on the parameter
+            if self.graph.nodes[param.0].cache.as_ref() != Some(&value) {
+                self.graph.set_input(name, value);
+            }
+        }
+        self.outputs
+            .iter()
+            .map(|&output| {
+                let (value, errors) = self.graph.compute_with_error(output);
+                for err in errors {
+                    let source = Box::new(err);
+                    report(eval_errors, EvalError::Call { node, source });
+                }
+                value
+            })
+            .collect()
+    }
+}
+
+impl<T: Clone + PartialEq + 'static> CompGraph<T> {
+    /// Instantiates `subgraph` as a single node with `args` as values of its parameters,
+    /// returns nodes of its outputs.
+    ///
+    /// Every instance has its own copy of the subgraph, which keeps caches of its nodes,
+    /// so only parts depending on changed arguments are recomputed.
+    pub fn call(
+        &mut self,
+        subgraph: &SubGraph<T>,
+        args: impl IntoIterator<Item = NodeId>,
+    ) -> Vec<NodeId> {
+        let args = args.into_iter().collect::<Vec<_>>();
+        let mut instance = Instance::new(subgraph);
+        assert_eq!(
+            args.len(),
+            instance.param_count(),
+            "wrong number of arguments for subgraph"
+        );
+        let outputs = instance.output_count();
+        // the node evaluating the instance is added first by `add_multi_output_node`
+        let node = NodeId(self.nodes.len());
+        let eval_errors = self.eval_errors.clone();
+        let ports = self.add_multi_output_node(args, outputs, move |values| {
+            instance.eval(values, node, &eval_errors)
+        });
+        self.calls.insert(node.0, subgraph.clone());
+        ports
+    }
+}
+
+impl<T> CompGraph<T> {
+    /// Adds nodes of `subgraph` directly into this graph with `args` as values of its parameters,
+    /// returns nodes of its outputs.
+    ///
+    /// Unlike [`call`](Self::call) the result consists only of ordinary nodes,
+    /// so it can be e.g. compiled to bytecode if the subgraph uses only builtin operations.
+    pub fn inline_call(
+        &mut self,
+        subgraph: &SubGraph<T>,
+        args: impl IntoIterator<Item = NodeId>,
+    ) -> Vec<NodeId> {
+        let args = args.into_iter().collect::<Vec<_>>();
+        subgraph.build_into(self, &args)
+    }
+
+    /// Replaces subgraph call `node`, or any of its outputs, with nodes of the subgraph
+    /// as [`inline_call`](Self::inline_call) would add them, returns nodes of its outputs.
+    ///
+    /// Nodes using outputs of the call are rewired to the new nodes,
+    /// the call itself is kept, so its ids still refer to the same values.
+    pub fn inline_node(&mut self, node: NodeId) -> Result<Vec<NodeId>, RewireError> {
+        let call = match self.nodes[node.0].kind {
+            OpKind::Port(_) => self.nodes[node.0].node_inputs[0],
+            _ => node,
+        };
+        let subgraph = self
+            .calls
+            .remove(&call.0)
+            .ok_or(RewireError::NotCall(node))?;
+        let args = self.nodes[call.0].node_inputs.to_vec();
+        let outputs = subgraph.build_into(self, &args);
+
+        let ports = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(id, port)| match port.kind {
+                OpKind::Port(index) if port.node_inputs[0] == call => Some((id, outputs[index])),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        for id in 0..self.nodes.len() {
+            let node_inputs = &self.nodes[id].node_inputs;
+            if node_inputs.iter().any(|input| ports.contains_key(&input.0)) {
+                let node_inputs = node_inputs
+                    .iter()
+                    .map(|input| ports.get(&input.0).copied().unwrap_or(*input))
+                    .collect();
+                self.replace_inputs(NodeId(id), node_inputs);
+            }
+        }
+        Ok(outputs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::BuiltinOp;
+    use std::cell::Cell;
+
+    fn scaled_square(calls: Rc<Cell<u32>>) -> SubGraph<f64> {
+        SubGraph::new(["x", "scale"], ["result"], move |graph, params| {
+            let calls = calls.clone();
+            let square = graph.add_node([params[0]], move |x| {
+                calls.set(calls.get() + 1);
+                let x = x.next().unwrap();
+                x * x
+            });
+            vec![graph.add_op(BuiltinOp::Mul, [square, params[1]])]
+        })
+    }
+
+    #[test]
+    fn test_call() {
+        let calls = Rc::new(Cell::new(0));
+        let subgraph = scaled_square(calls.clone());
+        assert_eq!(subgraph.params().collect::<Vec<_>>(), ["x", "scale"]);
+        assert_eq!(subgraph.output_index("result"), Some(0));
+
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let y = graph.add_input_node("y");
+        let scale = graph.add_input_node("scale");
+        let first = graph.call(&subgraph, [x, scale])[0];
+        let second = graph.call(&subgraph, [y, scale])[0];
+        let result = graph.add_op(BuiltinOp::Add, [first, second]);
+        graph.set_input("x", 2.0);
+        graph.set_input("y", 3.0);
+        graph.set_input("scale", 10.0);
+
+        assert_eq!(graph.compute(result), 130.0);
+        assert_eq!(calls.get(), 2);
+        // each instance keeps its own cache of the square
+        graph.set_input("scale", 2.0);
+        assert_eq!(graph.compute(result), 26.0);
+        assert_eq!(calls.get(), 2);
+        graph.set_input("y", 1.0);
+        assert_eq!(graph.compute(result), 10.0);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn test_inline_call() {
+        let subgraph = SubGraph::new(["x", "y"], ["sum", "product"], |graph, params| {
+            vec![
+                graph.add_op(BuiltinOp::Add, params.iter().copied()),
+                graph.add_op(BuiltinOp::Mul, params.iter().copied()),
+            ]
+        });
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let y = graph.add_input_node("y");
+        let outputs = graph.inline_call(&subgraph, [x, y]);
+        let result = graph.add_op(BuiltinOp::Sub, outputs);
+
+        let program = graph.compile(result).unwrap();
+        assert_eq!(program.run(&[2.0f32, 3.0]), -1.0);
+        graph.set_input("x", 2.0);
+        graph.set_input("y", 3.0);
+        assert_eq!(graph.compute(result), -1.0);
+    }
+
+    #[test]
+    fn test_inline_node() {
+        let subgraph = SubGraph::new(["x", "y"], ["sum", "product"], |graph, params| {
+            vec![
+                graph.add_op(BuiltinOp::Add, params.iter().copied()),
+                graph.add_op(BuiltinOp::Mul, params.iter().copied()),
+            ]
+        });
+        let mut graph = CompGraph::new();
+        let x = graph.add_input_node("x");
+        let y = graph.add_input_node("y");
+        let outputs = graph.call(&subgraph, [x, y]);
+        let result = graph.add_op(BuiltinOp::Sub, outputs.iter().copied());
+        graph.set_input("x", 2.0f32);
+        graph.set_input("y", 3.0);
+        assert_eq!(graph.compute(result), -1.0);
+        assert!(graph.compile(result).is_err());
+
+        let inlined = graph.inline_node(outputs[1]).unwrap();
+        assert_eq!(graph.node(result).inputs(), inlined);
+        assert_eq!(graph.cache(result), None);
+        assert_eq!(graph.compute(result), -1.0);
+        assert_eq!(graph.compile(result).unwrap().run(&[1.0, 4.0]), 1.0);
+        // the call still computes the same values
+        graph.set_input("x", 1.0);
+        assert_eq!(graph.compute(outputs[0]), 4.0);
+        assert_eq!(
+            graph.inline_node(outputs[0]),
+            Err(RewireError::NotCall(outputs[0]))
+        );
+        assert_eq!(graph.inline_node(x), Err(RewireError::NotCall(x)));
+    }
+}
@@ -0,0 +1,17 @@
+// straightforward version that corresponds to the API of the example in task description
+pub mod comp_graph;
+// zero-allocation static dispatch version with heterogeneous nodes if the performance is critical
+// and graph is known beforehand
+pub mod comp_graph2;
+// most readable and maintainable arena-based version that is fast enough for most cases
+pub mod comp_graph3;
+// named operations shared by all versions
+pub mod ops;
+// infix printing of expressions built by comp_graph and comp_graph3
+pub mod pretty;
+// spreadsheet of cells with formulas built on comp_graph3
+pub mod spreadsheet;
+// cache counters of all versions
+pub mod stats;
+// structured events for diagnosing recomputation
+pub mod trace;
@@ -0,0 +1,318 @@
+use smallvec::SmallVec;
+use std::fmt::{Debug, Display, Formatter};
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// Numeric type that builtin operations can be applied to
+pub trait Scalar:
+    Copy
+    + PartialOrd
+    + Debug
+    + Display
+    + 'static
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+    /// Name of the type in Rust source code
+    const TYPE_NAME: &'static str;
+
+    fn from_f64(value: f64) -> Self;
+    fn to_f64(self) -> f64;
+
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    fn tan(self) -> Self;
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn powf(self, n: Self) -> Self;
+    fn abs(self) -> Self;
+}
+
+macro_rules! impl_scalar {
+    ($($ty:ident)*) => {$(
+        impl Scalar for $ty {
+            const ZERO: Self = 0.0;
+            const ONE: Self = 1.0;
+            const TYPE_NAME: &'static str = stringify!($ty);
+
+            fn from_f64(value: f64) -> Self {
+                value as $ty
+            }
+
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn sin(self) -> Self {
+                $ty::sin(self)
+            }
+
+            fn cos(self) -> Self {
+                $ty::cos(self)
+            }
+
+            fn tan(self) -> Self {
+                $ty::tan(self)
+            }
+
+            fn exp(self) -> Self {
+                $ty::exp(self)
+            }
+
+            fn ln(self) -> Self {
+                $ty::ln(self)
+            }
+
+            fn sqrt(self) -> Self {
+                $ty::sqrt(self)
+            }
+
+            fn powf(self, n: Self) -> Self {
+                $ty::powf(self, n)
+            }
+
+            fn abs(self) -> Self {
+                $ty::abs(self)
+            }
+        }
+    )*};
+}
+
+impl_scalar!(f32 f64);
+
+/// Standard operations known to every graph flavour by name.
+///
+/// Comparisons produce `1` for true and `0` for false,
+/// `Select` picks its second argument if the first one is non-zero and the third one otherwise.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BuiltinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Sin,
+    Cos,
+    Tan,
+    Exp,
+    Ln,
+    Sqrt,
+    Pow,
+    Min,
+    Max,
+    Abs,
+    Clamp,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    Select,
+}
+
+impl BuiltinOp {
+    pub const ALL: [BuiltinOp; 23] = [
+        BuiltinOp::Add,
+        BuiltinOp::Sub,
+        BuiltinOp::Mul,
+        BuiltinOp::Div,
+        BuiltinOp::Neg,
+        BuiltinOp::Sin,
+        BuiltinOp::Cos,
+        BuiltinOp::Tan,
+        BuiltinOp::Exp,
+        BuiltinOp::Ln,
+        BuiltinOp::Sqrt,
+        BuiltinOp::Pow,
+        BuiltinOp::Min,
+        BuiltinOp::Max,
+        BuiltinOp::Abs,
+        BuiltinOp::Clamp,
+        BuiltinOp::Lt,
+        BuiltinOp::Le,
+        BuiltinOp::Gt,
+        BuiltinOp::Ge,
+        BuiltinOp::Eq,
+        BuiltinOp::Ne,
+        BuiltinOp::Select,
+    ];
+
+    /// Stable name of the operation, used for printing and serialization
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinOp::Add => "add",
+            BuiltinOp::Sub => "sub",
+            BuiltinOp::Mul => "mul",
+            BuiltinOp::Div => "div",
+            BuiltinOp::Neg => "neg",
+            BuiltinOp::Sin => "sin",
+            BuiltinOp::Cos => "cos",
+            BuiltinOp::Tan => "tan",
+            BuiltinOp::Exp => "exp",
+            BuiltinOp::Ln => "ln",
+            BuiltinOp::Sqrt => "sqrt",
+            BuiltinOp::Pow => "pow",
+            BuiltinOp::Min => "min",
+            BuiltinOp::Max => "max",
+            BuiltinOp::Abs => "abs",
+            BuiltinOp::Clamp => "clamp",
+            BuiltinOp::Lt => "lt",
+            BuiltinOp::Le => "le",
+            BuiltinOp::Gt => "gt",
+            BuiltinOp::Ge => "ge",
+            BuiltinOp::Eq => "eq",
+            BuiltinOp::Ne => "ne",
+            BuiltinOp::Select => "select",
+        }
+    }
+
+    /// Looks up operation by its [`name`](Self::name)
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|op| op.name() == name)
+    }
+
+    /// Number of inputs operation expects
+    pub fn arity(self) -> usize {
+        match self {
+            BuiltinOp::Neg
+            | BuiltinOp::Sin
+            | BuiltinOp::Cos
+            | BuiltinOp::Tan
+            | BuiltinOp::Exp
+            | BuiltinOp::Ln
+            | BuiltinOp::Sqrt
+            | BuiltinOp::Abs => 1,
+            BuiltinOp::Clamp | BuiltinOp::Select => 3,
+            _ => 2,
+        }
+    }
+
+    /// Applies operation to `args`, which must contain exactly [`arity`](Self::arity) values
+    pub fn apply<T: Scalar>(self, args: &[T]) -> T {
+        assert_eq!(
+            args.len(),
+            self.arity(),
+            "wrong number of arguments for {}",
+            self
+        );
+        let truth = |x: bool| if x { T::ONE } else { T::ZERO };
+        match *args {
+            [x] => match self {
+                BuiltinOp::Neg => -x,
+                BuiltinOp::Sin => x.sin(),
+                BuiltinOp::Cos => x.cos(),
+                BuiltinOp::Tan => x.tan(),
+                BuiltinOp::Exp => x.exp(),
+                BuiltinOp::Ln => x.ln(),
+                BuiltinOp::Sqrt => x.sqrt(),
+                BuiltinOp::Abs => x.abs(),
+                _ => unreachable!(),
+            },
+            [x, y] => match self {
+                BuiltinOp::Add => x + y,
+                BuiltinOp::Sub => x - y,
+                BuiltinOp::Mul => x * y,
+                BuiltinOp::Div => x / y,
+                BuiltinOp::Pow => x.powf(y),
+                BuiltinOp::Min => min(x, y),
+                BuiltinOp::Max => max(x, y),
+                BuiltinOp::Lt => truth(x < y),
+                BuiltinOp::Le => truth(x <= y),
+                BuiltinOp::Gt => truth(x > y),
+                BuiltinOp::Ge => truth(x >= y),
+                BuiltinOp::Eq => truth(x == y),
+                BuiltinOp::Ne => truth(x != y),
+                _ => unreachable!(),
+            },
+            [x, y, z] => match self {
+                BuiltinOp::Clamp => min(max(x, y), z),
+                BuiltinOp::Select => {
+                    if x != T::ZERO {
+                        y
+                    } else {
+                        z
+                    }
+                }
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    /// Same as [`apply`](Self::apply) but takes arguments in the form used by
+    /// [`comp_graph3::CompGraph`](crate::comp_graph3::CompGraph) operations.
+    ///
+    /// [`Select`](BuiltinOp::Select) takes only the chosen branch from `args`,
+    /// so the other one is not computed.
+    pub fn eval<T: Scalar>(self, args: &mut dyn Iterator<Item = T>) -> T {
+        if self == BuiltinOp::Select {
+            let condition = args.next().expect("condition of select");
+            let chosen = if condition != T::ZERO {
+                args.next()
+            } else {
+                args.nth(1)
+            };
+            return chosen.expect("branch of select");
+        }
+        let args: SmallVec<[T; 3]> = args.collect();
+        self.apply(&args)
+    }
+}
+
+impl Display for BuiltinOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// `f32::min`/`f32::max` are not available through `PartialOrd`
+fn min<T: Scalar>(x: T, y: T) -> T {
+    if y < x {
+        y
+    } else {
+        x
+    }
+}
+
+fn max<T: Scalar>(x: T, y: T) -> T {
+    if y > x {
+        y
+    } else {
+        x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        for op in BuiltinOp::ALL {
+            assert_eq!(BuiltinOp::from_name(op.name()), Some(op));
+        }
+        assert_eq!(BuiltinOp::from_name("nope"), None);
+    }
+
+    #[test]
+    fn test_apply() {
+        assert_eq!(BuiltinOp::Add.apply(&[1.0f32, 2.0]), 3.0);
+        assert_eq!(BuiltinOp::Pow.apply(&[2.0f64, 3.0]), 8.0);
+        assert_eq!(BuiltinOp::Clamp.apply(&[5.0f32, 0.0, 1.0]), 1.0);
+        assert_eq!(BuiltinOp::Lt.apply(&[1.0f32, 2.0]), 1.0);
+        assert_eq!(BuiltinOp::Select.apply(&[0.0f32, 2.0, 3.0]), 3.0);
+        assert_eq!(BuiltinOp::Abs.eval(&mut [-2.0f32].into_iter()), 2.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "wrong number of arguments for sin")]
+    fn test_arity_mismatch() {
+        BuiltinOp::Sin.apply(&[1.0f32, 2.0]);
+    }
+}
@@ -0,0 +1,343 @@
+//! Printing of nodes as infix expressions for debugging
+//!
+//! Nodes used several times are printed once as let-bindings preceding the expression,
+//! named by their labels if they have ones:
+//!
+//! ```text
+//! let t1 = x2 + x3^3;
+//! x1 * t1 + sin(t1)
+//! ```
+
+use crate::ops::BuiltinOp;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write};
+
+/// How a node is printed, not including its inputs
+#[derive(Clone, Debug, PartialEq)]
+pub enum Term<'a> {
+    /// Printed as is, e.g. name of an input or value of a constant
+    Atom(Cow<'a, str>),
+    /// Printed as an operator where it has one and as a function call otherwise
+    Builtin(BuiltinOp),
+    /// Printed as a function call
+    Call(Cow<'a, str>),
+}
+
+impl Term<'_> {
+    fn into_owned(self) -> Term<'static> {
+        match self {
+            Term::Atom(text) => Term::Atom(text.into_owned().into()),
+            Term::Builtin(op) => Term::Builtin(op),
+            Term::Call(name) => Term::Call(name.into_owned().into()),
+        }
+    }
+}
+
+/// Node that can be printed as an expression, with inputs borrowed for `'a`
+pub trait Expression<'a> {
+    /// Identifies the node, so that a node used several times is printed once
+    fn id(&self) -> usize;
+
+    fn term(&self) -> Term<'_>;
+
+    /// Name of the binding if the node is used several times
+    fn label(&self) -> Option<&str> {
+        None
+    }
+
+    /// Calls `f` for every input of the node, in order
+    fn for_each_input(&self, f: &mut dyn FnMut(Box<dyn Expression<'a> + 'a>));
+}
+
+/// Writes expression of `root`, preceded by let-bindings of shared nodes
+pub fn write<'a>(out: &mut dyn Write, root: &dyn Expression<'a>) -> fmt::Result {
+    let mut printer = Printer::default();
+    let root = printer.collect(root);
+    printer.bind_shared(root);
+    let texts = printer.render_all();
+    for (index, name) in printer.bindings.iter().enumerate() {
+        if let Some(name) = name {
+            writeln!(out, "let {} = {};", name, texts[index].0)?;
+        }
+    }
+    out.write_str(&texts[root].0)
+}
+
+const CMP: u8 = 0;
+const ADD: u8 = 1;
+const MUL: u8 = 2;
+const NEG: u8 = 3;
+const POW: u8 = 4;
+const ATOM: u8 = 5;
+
+/// Operator and precedence of builtin operations printed in infix notation
+fn infix(op: BuiltinOp) -> Option<(&'static str, u8)> {
+    Some(match op {
+        BuiltinOp::Add => (" + ", ADD),
+        BuiltinOp::Sub => (" - ", ADD),
+        BuiltinOp::Mul => (" * ", MUL),
+        BuiltinOp::Div => (" / ", MUL),
+        BuiltinOp::Pow => ("^", POW),
+        BuiltinOp::Lt => (" < ", CMP),
+        BuiltinOp::Le => (" <= ", CMP),
+        BuiltinOp::Gt => (" > ", CMP),
+        BuiltinOp::Ge => (" >= ", CMP),
+        BuiltinOp::Eq => (" == ", CMP),
+        BuiltinOp::Ne => (" != ", CMP),
+        _ => return None,
+    })
+}
+
+struct Entry {
+    term: Term<'static>,
+    label: Option<String>,
+    inputs: Vec<usize>,
+    uses: usize,
+}
+
+/// Node whose inputs are being collected
+struct Frame<'a> {
+    id: usize,
+    term: Term<'static>,
+    label: Option<String>,
+    // inputs not visited yet, the next one last
+    pending: Vec<Box<dyn Expression<'a> + 'a>>,
+    inputs: Vec<usize>,
+}
+
+impl<'a> Frame<'a> {
+    fn new(expr: &dyn Expression<'a>) -> Self {
+        let mut pending = vec![];
+        expr.for_each_input(&mut |input| pending.push(input));
+        pending.reverse();
+        Self {
+            id: expr.id(),
+            term: expr.term().into_owned(),
+            label: expr.label().map(str::to_owned),
+            inputs: Vec::with_capacity(pending.len()),
+            pending,
+        }
+    }
+}
+
+/// Nodes reachable from the root, each one after its inputs
+#[derive(Default)]
+struct Printer {
+    entries: Vec<Entry>,
+    indices: HashMap<usize, usize>,
+    bindings: Vec<Option<String>>,
+}
+
+impl Printer {
+    /// Adds entries of `root` and nodes it depends on, returns index of the root
+    fn collect<'a>(&mut self, root: &dyn Expression<'a>) -> usize {
+        // without recursion, as long chains of nodes would overflow the stack
+        let mut stack = vec![Frame::new(root)];
+        loop {
+            let frame = stack.last_mut().expect("root should be on the stack");
+            if let Some(input) = frame.pending.pop() {
+                match self.indices.get(&input.id()) {
+                    Some(&index) => {
+                        self.entries[index].uses += 1;
+                        frame.inputs.push(index);
+                    }
+                    None => stack.push(Frame::new(&*input)),
+                }
+                continue;
+            }
+            let frame = stack.pop().expect("root should be on the stack");
+            self.entries.push(Entry {
+                term: frame.term,
+                label: frame.label,
+                inputs: frame.inputs,
+                uses: 1,
+            });
+            let index = self.entries.len() - 1;
+            self.indices.insert(frame.id, index);
+            match stack.last_mut() {
+                Some(parent) => parent.inputs.push(index),
+                None => return index,
+            }
+        }
+    }
+
+    /// Names shared nodes other than atoms, avoiding names of the atoms
+    fn bind_shared(&mut self, root: usize) {
+        let mut taken = self
+            .entries
+            .iter()
+            .filter_map(|entry| match &entry.term {
+                Term::Atom(text) => Some(text.to_string()),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+        let mut next_temp = 1;
+        self.bindings = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                if index == root || entry.uses < 2 || matches!(entry.term, Term::Atom(_)) {
+                    return None;
+                }
+                let mut name = entry.label.clone().filter(|label| !taken.contains(label));
+                while name.is_none() {
+                    let temp = format!("t{}", next_temp);
+                    next_temp += 1;
+                    name = Some(temp).filter(|temp| !taken.contains(temp));
+                }
+                taken.extend(name.clone());
+                name
+            })
+            .collect();
+    }
+
+    /// Text and precedence of definitions of all nodes, rendered after their inputs.
+    ///
+    /// Texts of nodes printed inline are moved into their only user.
+    fn render_all(&self) -> Vec<(String, u8)> {
+        let mut texts = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            let text = self.render(entry, &mut texts);
+            texts.push(text);
+        }
+        texts
+    }
+
+    /// Text and precedence of the input at `index`
+    fn operand(&self, index: usize, texts: &mut [(String, u8)]) -> (String, u8) {
+        match (&self.bindings[index], &self.entries[index].term) {
+            (Some(name), _) => (name.clone(), ATOM),
+            // atoms are never bound, so they can be used several times
+            (None, Term::Atom(_)) => texts[index].clone(),
+            (None, _) => std::mem::take(&mut texts[index]),
+        }
+    }
+
+    /// Text and precedence of the definition of `entry`, given texts of the preceding nodes
+    fn render(&self, entry: &Entry, texts: &mut [(String, u8)]) -> (String, u8) {
+        let name = match &entry.term {
+            Term::Atom(text) => {
+                let precedence = if text.starts_with('-') { NEG } else { ATOM };
+                return (text.to_string(), precedence);
+            }
+            Term::Builtin(BuiltinOp::Neg) if entry.inputs.len() == 1 => {
+                let (operand, precedence) = self.operand(entry.inputs[0], texts);
+                return if precedence <= NEG {
+                    (format!("-({})", operand), NEG)
+                } else {
+                    (format!("-{}", operand), NEG)
+                };
+            }
+            &Term::Builtin(op) => match (infix(op), &entry.inputs[..]) {
+                (Some((symbol, precedence)), &[left, right]) => {
+                    let (left, left_precedence) = self.operand(left, texts);
+                    let (right, right_precedence) = self.operand(right, texts);
+                    // power is right-associative and comparisons are not associative
+                    let left_parens = left_precedence < precedence
+                        || left_precedence == precedence && matches!(precedence, POW | CMP);
+                    let right_parens = right_precedence < precedence
+                        || right_precedence == precedence && precedence != POW;
+                    // appending to the left operand keeps long left-nested chains linear
+                    let mut text = parenthesize(left, left_parens);
+                    text.push_str(symbol);
+                    text.push_str(&parenthesize(right, right_parens));
+                    return (text, precedence);
+                }
+                _ => op.name(),
+            },
+            Term::Call(name) => name,
+        };
+        let args = entry
+            .inputs
+            .iter()
+            .map(|&input| self.operand(input, texts).0)
+            .collect::<Vec<_>>();
+        (format!("{}({})", name, args.join(", ")), ATOM)
+    }
+}
+
+fn parenthesize(text: String, parens: bool) -> String {
+    if parens {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::comp_graph3::CompGraph;
+    use crate::ops::BuiltinOp;
+
+    #[test]
+    fn test_precedence() {
+        let mut graph = CompGraph::<f64>::new();
+        let [a, b, c] = ["a", "b", "c"].map(|name| graph.add_input_node(name));
+        let mut op = |op, inputs: &[_]| graph.add_op(op, inputs.iter().copied());
+        let sum = op(BuiltinOp::Add, &[a, b]);
+        let difference = op(BuiltinOp::Sub, &[a, b]);
+        let product = op(BuiltinOp::Mul, &[sum, c]);
+        let nested = op(BuiltinOp::Sub, &[c, difference]);
+        let power = op(BuiltinOp::Pow, &[a, product]);
+        let tower = op(BuiltinOp::Pow, &[power, b]);
+        let negated = op(BuiltinOp::Neg, &[tower]);
+        let neg_base = op(BuiltinOp::Neg, &[a]);
+        let square = op(BuiltinOp::Pow, &[neg_base, c]);
+        let less = op(BuiltinOp::Lt, &[nested, square]);
+        let clamped = op(BuiltinOp::Clamp, &[less, a, negated]);
+        let half = graph.add_constant(-0.5);
+        let scaled = graph.add_op(BuiltinOp::Pow, [half, a]);
+        let result = graph.add_op(BuiltinOp::Div, [clamped, scaled]);
+
+        assert_eq!(graph.node(product).to_string(), "(a + b) * c");
+        assert_eq!(graph.node(nested).to_string(), "c - (a - b)");
+        assert_eq!(graph.node(tower).to_string(), "(a^((a + b) * c))^b");
+        assert_eq!(
+            graph.node(result).to_string(),
+            "clamp(c - (a - b) < (-a)^c, a, -(a^((a + b) * c))^b) / (-0.5)^a"
+        );
+    }
+
+    #[test]
+    fn test_deep_chain() {
+        let mut graph = CompGraph::<f64>::new();
+        let x = graph.add_input_node("x");
+        let mut node = x;
+        for _ in 0..100_000 {
+            node = graph.add_op(BuiltinOp::Add, [node, x]);
+        }
+        let text = graph.node(node).to_string();
+        assert_eq!(text.len(), "x".len() + 100_000 * " + x".len());
+        assert!(text.starts_with("x + x + x"));
+    }
+
+    #[test]
+    fn test_shared_nodes() {
+        let mut graph = CompGraph::<f32>::new();
+        let x = graph.add_input_node("x");
+        let t1 = graph.add_input_node("t1");
+        let scale = graph.add_parameter("scale", 2.0);
+        let sum = graph.add_op(BuiltinOp::Add, [x, t1]);
+        let scaled = graph.add_op(BuiltinOp::Mul, [sum, scale]);
+        let sine = graph.add_op(BuiltinOp::Sin, [scaled]);
+        let cosine = graph.add_op(BuiltinOp::Cos, [scaled]);
+        let ratio = graph.add_op(BuiltinOp::Div, [sine, sum]);
+        let result = graph.add_node([ratio, cosine, x], |args| args.sum());
+
+        assert_eq!(
+            graph.node(result).to_string(),
+            "let t2 = x + t1;\n\
+             let t3 = t2 * scale;\n\
+             custom(sin(t3) / t2, cos(t3), x)"
+        );
+        graph.set_label(scaled, "scaled");
+        assert_eq!(
+            graph.node(result).to_string(),
+            "let t2 = x + t1;\n\
+             let scaled = t2 * scale;\n\
+             custom(sin(scaled) / t2, cos(scaled), x)"
+        );
+    }
+}
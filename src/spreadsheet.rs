@@ -0,0 +1,825 @@
+//! Spreadsheet whose cells are nodes of [`CompGraph`],
+//! so editing a cell recalculates only cells depending on it
+
+use crate::comp_graph3::{CompGraph, NodeId, OpKind};
+use crate::ops::BuiltinOp;
+use smallvec::{smallvec, SmallVec};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Address of a cell like `B3`, both indices start at 0
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CellRef {
+    pub column: u32,
+    pub row: u32,
+}
+
+/// Error of editing or reading cells of a [`Sheet`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum SheetError {
+    /// Cell address is not a column letters followed by a row number
+    InvalidReference(String),
+    /// Cell content is neither a number nor a formula starting with `=`
+    InvalidValue(String),
+    /// Formula can't be parsed, `position` is the byte offset in the formula after `=`
+    Syntax {
+        position: usize,
+        message: &'static str,
+    },
+    UnknownFunction(String),
+    /// Formula depends on its own cell, contains the references from the cell back to it
+    CircularReference(Vec<CellRef>),
+}
+
+impl Display for SheetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SheetError::InvalidReference(cell) => write!(f, "{} is not a valid cell", cell),
+            SheetError::InvalidValue(value) => write!(f, "{} is not a number or formula", value),
+            SheetError::Syntax { position, message } => {
+                write!(f, "{} at position {} of formula", message, position)
+            }
+            SheetError::UnknownFunction(name) => write!(f, "unknown function {}", name),
+            SheetError::CircularReference(path) => {
+                write!(f, "circular reference ")?;
+                for (i, cell) in path.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " -> ")?;
+                    }
+                    write!(f, "{}", cell)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for SheetError {}
+
+impl FromStr for CellRef {
+    type Err = SheetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || SheetError::InvalidReference(s.to_owned());
+        let digits = s.find(|c: char| c.is_ascii_digit()).ok_or_else(invalid)?;
+        let (letters, row) = s.split_at(digits);
+        if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Err(invalid());
+        }
+        if !row.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        // bijective base 26: A = 1, Z = 26, AA = 27
+        let column = letters.bytes().try_fold(0u32, |column, letter| {
+            let digit = (letter.to_ascii_uppercase() - b'A') as u32 + 1;
+            column.checked_mul(26)?.checked_add(digit)
+        });
+        let row = row.parse::<u32>().ok().filter(|&row| row > 0);
+        match (column, row) {
+            (Some(column), Some(row)) => Ok(CellRef {
+                column: column - 1,
+                row: row - 1,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Display for CellRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut letters = vec![];
+        let mut column = self.column as u64 + 1;
+        while column > 0 {
+            column -= 1;
+            letters.push(b'A' + (column % 26) as u8);
+            column /= 26;
+        }
+        letters.reverse();
+        write!(
+            f,
+            "{}{}",
+            String::from_utf8(letters).expect("letters are ascii"),
+            self.row as u64 + 1
+        )
+    }
+}
+
+/// Cells holding numbers or formulas like `=SUM(A1:A3) * B1`.
+///
+/// Formulas support `+ - * / ^`, parentheses, cell references, builtin operations
+/// as functions (`SQRT(A1)`, `MIN(A1, 2)`), `IF(condition, a, b)` and `SUM` of values and ranges.
+/// `IF` computes only the chosen branch. Empty cells have value 0.
+pub struct Sheet {
+    graph: CompGraph<f64>,
+    cells: HashMap<CellRef, Cell>,
+    // nodes of released cells and replaced formulas, reused for new ones
+    free: Vec<NodeId>,
+}
+
+struct Cell {
+    node: NodeId,
+    content: String,
+    // cells the formula reads, used to detect cycles
+    refs: Vec<CellRef>,
+    // nodes computing parts of the formula, the whole formula is computed by `node`
+    parts: Vec<NodeId>,
+    // number of cells whose formulas read this one
+    readers: usize,
+}
+
+impl Sheet {
+    pub fn new() -> Self {
+        Self {
+            graph: CompGraph::new(),
+            cells: HashMap::new(),
+            free: vec![],
+        }
+    }
+
+    /// Sets content of `cell`, which is a finite number, a formula starting with `=` or empty.
+    ///
+    /// The cell stays unchanged if the content is invalid or creates a circular reference.
+    pub fn set(&mut self, cell: &str, content: &str) -> Result<(), SheetError> {
+        let cell = cell.parse()?;
+        let content = content.trim();
+        let (formula, refs) = if let Some(formula) = content.strip_prefix('=') {
+            Parser::new(formula).parse()?
+        } else if content.is_empty() {
+            (vec![Item::Number(0.0)], vec![])
+        } else {
+            let value = content
+                .parse()
+                .ok()
+                .filter(|value: &f64| value.is_finite())
+                .ok_or_else(|| SheetError::InvalidValue(content.to_owned()))?;
+            (vec![Item::Number(value)], vec![])
+        };
+        if let Some(path) = self.find_cycle(cell, &refs) {
+            return Err(SheetError::CircularReference(path));
+        }
+
+        let inputs = refs
+            .iter()
+            .map(|&input| {
+                let input = self.cell(input);
+                input.readers += 1;
+                input.node
+            })
+            .collect::<Vec<_>>();
+        let current = self.cell(cell);
+        let node = current.node;
+        let parts = std::mem::take(&mut current.parts);
+        for part in parts {
+            self.release(part);
+        }
+        let parts = self.compile(node, &formula, &inputs);
+        let current = self.cell(cell);
+        current.content = content.to_owned();
+        current.parts = parts;
+        let old_refs = std::mem::replace(&mut current.refs, refs);
+        for input in old_refs {
+            self.cell(input).readers -= 1;
+            self.release_unused(input);
+        }
+        self.release_unused(cell);
+        Ok(())
+    }
+
+    /// Computes value of `cell`, recalculating only cells changed since the last time
+    pub fn value(&mut self, cell: &str) -> Result<f64, SheetError> {
+        let cell = cell.parse()?;
+        Ok(match self.cells.get(&cell) {
+            Some(cell) => self.graph.compute(cell.node),
+            None => 0.0,
+        })
+    }
+
+    /// Content of `cell` as it was set, empty for empty cells
+    pub fn content(&self, cell: &str) -> Result<&str, SheetError> {
+        let cell = cell.parse()?;
+        Ok(self
+            .cells
+            .get(&cell)
+            .map_or("", |cell| cell.content.as_str()))
+    }
+
+    fn cell(&mut self, cell: CellRef) -> &mut Cell {
+        if !self.cells.contains_key(&cell) {
+            let node = self.allocate();
+            self.cells.insert(
+                cell,
+                Cell {
+                    node,
+                    content: String::new(),
+                    refs: vec![],
+                    parts: vec![],
+                    readers: 0,
+                },
+            );
+        }
+        self.cells.get_mut(&cell).expect("cell was inserted")
+    }
+
+    /// Node not used by any cell, to be rewired for a new one
+    fn allocate(&mut self) -> NodeId {
+        self.free
+            .pop()
+            .unwrap_or_else(|| self.graph.add_node([], |_| 0.0))
+    }
+
+    /// Detaches `node` from its inputs, so it can be reused
+    fn release(&mut self, node: NodeId) {
+        self.graph
+            .rewire(node, SmallVec::new(), OpKind::Custom, |_| 0.0);
+        self.free.push(node);
+    }
+
+    /// Removes `cell` if it is empty and no formula reads it
+    fn release_unused(&mut self, cell: CellRef) {
+        if let Some(unused) = self.cells.get(&cell) {
+            if unused.content.is_empty() && unused.readers == 0 {
+                let unused = self.cells.remove(&cell).expect("cell was found");
+                for node in unused.parts.into_iter().chain([unused.node]) {
+                    self.release(node);
+                }
+            }
+        }
+    }
+
+    /// Rewires `node` to compute `formula` reading cell nodes `inputs`,
+    /// returns nodes added for its parts
+    fn compile(&mut self, node: NodeId, formula: &[Item], inputs: &[NodeId]) -> Vec<NodeId> {
+        let mut parts = vec![];
+        // nodes of values of the items not taken by other items yet
+        let mut values = vec![];
+        for (position, item) in formula.iter().enumerate() {
+            let target = if position + 1 == formula.len() {
+                node
+            } else if let Item::Ref(index) = *item {
+                values.push(inputs[index]);
+                continue;
+            } else {
+                let part = self.allocate();
+                parts.push(part);
+                part
+            };
+            match *item {
+                Item::Number(value) => {
+                    self.graph
+                        .rewire(target, SmallVec::new(), OpKind::Custom, move |_| value)
+                }
+                Item::Ref(index) => {
+                    self.graph
+                        .rewire(target, smallvec![inputs[index]], OpKind::Custom, |args| {
+                            args.next().unwrap()
+                        })
+                }
+                Item::Op(op) => {
+                    let args = values.split_off(values.len() - op.arity());
+                    // a select computes only the chosen branch
+                    self.graph
+                        .rewire(target, args.into(), OpKind::Builtin(op), move |args| {
+                            op.eval(args)
+                        })
+                }
+                Item::Sum(count) => {
+                    let args = values.split_off(values.len() - count);
+                    self.graph
+                        // an empty `sum` of floats is -0.0
+                        .rewire(target, args.into(), OpKind::Custom, |args| {
+                            args.fold(0.0, |sum, arg| sum + arg)
+                        })
+                }
+            }
+            values.push(target);
+        }
+        parts
+    }
+
+    /// Path of references from `cell` back to it if it would read `refs`
+    fn find_cycle(&self, cell: CellRef, refs: &[CellRef]) -> Option<Vec<CellRef>> {
+        let mut visited = HashSet::new();
+        let mut path = vec![cell];
+        // number of references already followed from each cell of the path
+        let mut followed = vec![0];
+        while let Some(&current) = path.last() {
+            let depth = path.len() - 1;
+            let current_refs = match depth {
+                0 => refs,
+                _ => self.cells.get(&current).map_or(&[][..], |cell| &cell.refs),
+            };
+            match current_refs.get(followed[depth]) {
+                Some(&next) => {
+                    followed[depth] += 1;
+                    if next == cell {
+                        path.push(next);
+                        return Some(path);
+                    }
+                    if visited.insert(next) {
+                        path.push(next);
+                        followed.push(0);
+                    }
+                }
+                None => {
+                    path.pop();
+                    followed.pop();
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Default for Sheet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Part of a parsed formula, which is a list of items in postfix order,
+/// each taking its arguments from the values of the items before it
+enum Item {
+    Number(f64),
+    /// Value of referenced cell, index in the list of references of the formula
+    Ref(usize),
+    Op(BuiltinOp),
+    /// Sum of the given number of values
+    Sum(usize),
+}
+
+/// Operator or group waiting for its operands while parsing
+enum Pending {
+    Op(BuiltinOp),
+    Paren,
+    /// Function with the number of its arguments parsed so far, `None` for `SUM`
+    Call {
+        op: Option<BuiltinOp>,
+        args: usize,
+    },
+}
+
+/// How tightly an operator binds its operands, so -2^2 is -4 and -2*3 is (-2)*3
+fn precedence(op: BuiltinOp) -> u8 {
+    match op {
+        BuiltinOp::Add | BuiltinOp::Sub => 1,
+        BuiltinOp::Mul | BuiltinOp::Div => 2,
+        BuiltinOp::Neg => 3,
+        _ => 4,
+    }
+}
+
+/// Shunting-yard parser of formulas, which keeps nesting on the heap rather than the call stack
+struct Parser<'a> {
+    formula: &'a [u8],
+    position: usize,
+    refs: Vec<CellRef>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(formula: &'a str) -> Self {
+        Self {
+            formula: formula.as_bytes(),
+            position: 0,
+            refs: vec![],
+        }
+    }
+
+    fn parse(mut self) -> Result<(Vec<Item>, Vec<CellRef>), SheetError> {
+        let mut items = vec![];
+        let mut pending = vec![];
+        // whether a value is expected next rather than an operator
+        let mut expect_value = true;
+        // whether the last argument of the innermost call is a range, which ends the argument
+        let mut after_range = false;
+        loop {
+            if expect_value {
+                match self.peek() {
+                    Some(b'-') => {
+                        self.position += 1;
+                        pending.push(Pending::Op(BuiltinOp::Neg));
+                    }
+                    Some(b'(') => {
+                        self.position += 1;
+                        pending.push(Pending::Paren);
+                    }
+                    Some(c) if c.is_ascii_digit() || c == b'.' => {
+                        items.push(Item::Number(self.number()?));
+                        expect_value = false;
+                    }
+                    Some(c) if c.is_ascii_alphabetic() => {
+                        let start = self.position;
+                        let name = self.take_while(|c| c.is_ascii_alphanumeric());
+                        if self.eat(b'(') {
+                            let op = function(name)?;
+                            if self.eat(b')') {
+                                items.push(self.finish_call(op, 0)?);
+                                expect_value = false;
+                            } else {
+                                pending.push(Pending::Call { op, args: 0 });
+                                after_range = self.range_argument(&mut items, &mut pending)?;
+                                expect_value = !after_range;
+                            }
+                        } else {
+                            self.position = start;
+                            let cell = self.cell()?;
+                            items.push(self.reference(cell));
+                            expect_value = false;
+                        }
+                    }
+                    _ => return Err(self.error("expected value")),
+                }
+                continue;
+            }
+
+            let op = match self.peek() {
+                Some(b'+') => BuiltinOp::Add,
+                Some(b'-') => BuiltinOp::Sub,
+                Some(b'*') => BuiltinOp::Mul,
+                Some(b'/') => BuiltinOp::Div,
+                Some(b'^') => BuiltinOp::Pow,
+                Some(b')') => {
+                    match close_group(&mut items, &mut pending) {
+                        Some(Pending::Paren) => self.position += 1,
+                        Some(Pending::Call { op, args }) => {
+                            self.position += 1;
+                            // arguments that are ranges are counted when parsed
+                            let args = if after_range { args } else { args + 1 };
+                            items.push(self.finish_call(op, args)?);
+                            after_range = false;
+                        }
+                        _ => return Err(self.error("unexpected character")),
+                    }
+                    continue;
+                }
+                Some(b',') => {
+                    match close_group(&mut items, &mut pending) {
+                        Some(Pending::Call { op, args }) => {
+                            self.position += 1;
+                            let args = if after_range { args } else { args + 1 };
+                            pending.push(Pending::Call { op, args });
+                            after_range = self.range_argument(&mut items, &mut pending)?;
+                            expect_value = !after_range;
+                        }
+                        Some(Pending::Paren) => return Err(self.error("expected )")),
+                        _ => return Err(self.error("unexpected character")),
+                    }
+                    continue;
+                }
+                next => {
+                    let group = pending
+                        .iter()
+                        .rev()
+                        .find(|pending| !matches!(pending, Pending::Op(_)));
+                    let message = match group {
+                        Some(Pending::Paren) => "expected )",
+                        Some(_) => "expected , or )",
+                        None if next.is_some() => "unexpected character",
+                        None => break,
+                    };
+                    return Err(self.error(message));
+                }
+            };
+            if after_range {
+                return Err(self.error("expected , or )"));
+            }
+            // power is right-associative, other binary operators are left-associative
+            while let Some(&Pending::Op(top)) = pending.last() {
+                if precedence(top) < precedence(op)
+                    || precedence(top) == precedence(op) && op == BuiltinOp::Pow
+                {
+                    break;
+                }
+                items.push(Item::Op(top));
+                pending.pop();
+            }
+            self.position += 1;
+            pending.push(Pending::Op(op));
+            expect_value = true;
+        }
+        close_group(&mut items, &mut pending);
+        Ok((items, self.refs))
+    }
+
+    fn error(&self, message: &'static str) -> SheetError {
+        SheetError::Syntax {
+            position: self.position,
+            message,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .formula
+            .get(self.position)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.formula.get(self.position).copied()
+    }
+
+    fn eat(&mut self, expected: u8) -> bool {
+        let found = self.peek() == Some(expected);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> &'a str {
+        let start = self.position;
+        while self.formula.get(self.position).is_some_and(|&c| f(c)) {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.formula[start..self.position]).expect("ascii is valid utf-8")
+    }
+
+    fn number(&mut self) -> Result<f64, SheetError> {
+        let start = self.position;
+        let number = self.take_while(|c| c.is_ascii_digit() || c == b'.');
+        number.parse().map_err(|_| SheetError::Syntax {
+            position: start,
+            message: "invalid number",
+        })
+    }
+
+    fn cell(&mut self) -> Result<CellRef, SheetError> {
+        self.skip_whitespace();
+        let start = self.position;
+        let letters = self.take_while(|c| c.is_ascii_alphabetic()).len();
+        let digits = self.take_while(|c| c.is_ascii_digit()).len();
+        let cell = std::str::from_utf8(&self.formula[start..start + letters + digits])
+            .expect("ascii is valid utf-8");
+        cell.parse().map_err(|_| SheetError::Syntax {
+            position: start,
+            message: "invalid cell reference",
+        })
+    }
+
+    fn reference(&mut self, cell: CellRef) -> Item {
+        let index = match self.refs.iter().position(|&other| other == cell) {
+            Some(index) => index,
+            None => {
+                self.refs.push(cell);
+                self.refs.len() - 1
+            }
+        };
+        Item::Ref(index)
+    }
+
+    fn finish_call(&self, op: Option<BuiltinOp>, args: usize) -> Result<Item, SheetError> {
+        match op {
+            None => Ok(Item::Sum(args)),
+            Some(op) if op.arity() == args => Ok(Item::Op(op)),
+            Some(_) => Err(self.error("wrong number of arguments")),
+        }
+    }
+
+    /// Parses the next argument of the innermost call if it is a range,
+    /// returns whether it was one
+    fn range_argument(
+        &mut self,
+        items: &mut Vec<Item>,
+        pending: &mut [Pending],
+    ) -> Result<bool, SheetError> {
+        let Some(Pending::Call { op, args }) = pending.last_mut() else {
+            unreachable!("argument should be parsed inside a call")
+        };
+        let Some(cells) = self.range(op.is_none())? else {
+            return Ok(false);
+        };
+        *args += cells.len();
+        items.extend(cells);
+        Ok(true)
+    }
+
+    /// References to all cells of a range like `A1:B3` if it is next and `allowed`
+    fn range(&mut self, allowed: bool) -> Result<Option<Vec<Item>>, SheetError> {
+        let start = self.position;
+        let Ok(first) = self.cell() else {
+            self.position = start;
+            return Ok(None);
+        };
+        if !self.eat(b':') {
+            self.position = start;
+            return Ok(None);
+        }
+        if !allowed {
+            return Err(self.error("ranges are supported only in SUM"));
+        }
+        let last = self.cell()?;
+        let mut cells = vec![];
+        for column in first.column.min(last.column)..=first.column.max(last.column) {
+            for row in first.row.min(last.row)..=first.row.max(last.row) {
+                cells.push(self.reference(CellRef { column, row }));
+            }
+        }
+        Ok(Some(cells))
+    }
+}
+
+/// Operation of function `name`, `None` for `SUM`
+fn function(name: &str) -> Result<Option<BuiltinOp>, SheetError> {
+    let name = name.to_ascii_lowercase();
+    match name.as_str() {
+        "sum" => Ok(None),
+        "if" => Ok(Some(BuiltinOp::Select)),
+        name => BuiltinOp::from_name(name)
+            .map(Some)
+            .ok_or_else(|| SheetError::UnknownFunction(name.to_uppercase())),
+    }
+}
+
+/// Moves operators pending since the innermost parenthesis or call to `items`,
+/// returns that group removed from `pending`
+fn close_group(items: &mut Vec<Item>, pending: &mut Vec<Pending>) -> Option<Pending> {
+    while let Some(top) = pending.pop() {
+        match top {
+            Pending::Op(op) => items.push(Item::Op(op)),
+            group => return Some(group),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cell_ref() {
+        for name in ["A1", "Z10", "AA3", "BC100"] {
+            assert_eq!(name.parse::<CellRef>().unwrap().to_string(), name);
+        }
+        assert_eq!("b2".parse::<CellRef>(), Ok(CellRef { column: 1, row: 1 }));
+        for invalid in ["", "A", "1", "A0", "1A", "A1B"] {
+            assert_eq!(
+                invalid.parse::<CellRef>(),
+                Err(SheetError::InvalidReference(invalid.to_owned()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_formulas() {
+        let mut sheet = Sheet::new();
+        // forward references to cells that are set later
+        sheet.set("C1", "=SUM(A1:B2) * 2 - A1^2").unwrap();
+        sheet.set("A1", "3").unwrap();
+        sheet.set("A2", "= IF(gt(A1, 2), sqrt(16), -1)").unwrap();
+        sheet.set("B1", "0.5").unwrap();
+        assert_eq!(sheet.value("C1"), Ok(6.0));
+        assert_eq!(sheet.value("B2"), Ok(0.0));
+
+        sheet.set("A1", "1").unwrap();
+        assert_eq!(sheet.value("A2"), Ok(-1.0));
+        assert_eq!(sheet.value("C1"), Ok(0.0));
+        // re-editing a formula rewires the cell
+        sheet.set("A2", "=B1 * 4").unwrap();
+        sheet.set("B1", "1").unwrap();
+        assert_eq!(sheet.value("C1"), Ok(11.0));
+        assert_eq!(sheet.content("A2"), Ok("=B1 * 4"));
+        sheet.set("A2", "").unwrap();
+        assert_eq!(sheet.value("C1"), Ok(3.0));
+    }
+
+    #[test]
+    fn test_circular_reference() {
+        let mut sheet = Sheet::new();
+        sheet.set("A1", "=B1 + 1").unwrap();
+        sheet.set("B1", "=C1 * 2").unwrap();
+        sheet.set("C1", "5").unwrap();
+
+        let path = ["C1", "A1", "B1", "C1"].map(|cell| cell.parse().unwrap());
+        let err = sheet.set("C1", "=A1").unwrap_err();
+        assert_eq!(err, SheetError::CircularReference(path.to_vec()));
+        assert_eq!(err.to_string(), "circular reference C1 -> A1 -> B1 -> C1");
+        assert!(matches!(
+            sheet.set("D1", "=D1"),
+            Err(SheetError::CircularReference(_))
+        ));
+        // rejected edits keep the previous content
+        assert_eq!(sheet.content("C1"), Ok("5"));
+        assert_eq!(sheet.value("A1"), Ok(11.0));
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut sheet = Sheet::new();
+        let syntax = |position, message| Err(SheetError::Syntax { position, message });
+        assert_eq!(sheet.set("A1", "=1 +"), syntax(3, "expected value"));
+        assert_eq!(sheet.set("A1", "=(1"), syntax(2, "expected )"));
+        assert_eq!(
+            sheet.set("A1", "=min(1)"),
+            syntax(6, "wrong number of arguments")
+        );
+        assert_eq!(sheet.set("A1", "=A1:A2"), syntax(2, "unexpected character"));
+        assert_eq!(
+            sheet.set("A1", "=foo(1)"),
+            Err(SheetError::UnknownFunction("FOO".to_owned()))
+        );
+        assert_eq!(
+            sheet.set("A1", "abc"),
+            Err(SheetError::InvalidValue("abc".to_owned()))
+        );
+        for value in ["nan", "inf", "-inf"] {
+            assert_eq!(
+                sheet.set("A1", value),
+                Err(SheetError::InvalidValue(value.to_owned()))
+            );
+        }
+        assert_eq!(
+            sheet.value("1A"),
+            Err(SheetError::InvalidReference("1A".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_empty_sum() {
+        let mut sheet = Sheet::new();
+        sheet.set("A1", "=SUM()").unwrap();
+        let value = sheet.value("A1").unwrap();
+        assert_eq!(value, 0.0);
+        assert!(value.is_sign_positive());
+    }
+
+    #[test]
+    fn test_lazy_if() {
+        let mut sheet = Sheet::new();
+        sheet.set("A1", "=IF(B1, C1 * 2, SQRT(D1))").unwrap();
+        sheet.set("B1", "1").unwrap();
+        sheet.set("C1", "3").unwrap();
+        sheet.set("D1", "=C1 + 1").unwrap();
+        assert_eq!(sheet.value("A1"), Ok(6.0));
+        let d1 = sheet.cells[&"D1".parse().unwrap()].node;
+        assert_eq!(sheet.graph.node(d1).cached(), None);
+
+        sheet.set("B1", "0").unwrap();
+        assert_eq!(sheet.value("A1"), Ok(2.0));
+        assert_eq!(sheet.graph.node(d1).cached(), Some(&4.0));
+    }
+
+    #[test]
+    fn test_release_empty_cells() {
+        let mut sheet = Sheet::new();
+        sheet.set("A1", "=B1 + SUM(C1:C3)").unwrap();
+        sheet.set("B1", "2").unwrap();
+        let node_count = sheet.graph.node_count();
+
+        // still read by A1
+        sheet.set("B1", "").unwrap();
+        assert_eq!(sheet.value("A1"), Ok(0.0));
+        assert_eq!(sheet.cells.len(), 5);
+        sheet.set("A1", "").unwrap();
+        assert!(sheet.cells.is_empty());
+
+        sheet.set("D1", "=E1 * 2").unwrap();
+        sheet.set("E1", "4").unwrap();
+        assert_eq!(sheet.value("D1"), Ok(8.0));
+        assert_eq!(sheet.graph.node_count(), node_count);
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        let depth = 100_000;
+        let formula = format!("={}1{}", "(".repeat(depth), ")".repeat(depth));
+        let mut sheet = Sheet::new();
+        sheet.set("A1", &formula).unwrap();
+        assert_eq!(sheet.value("A1"), Ok(1.0));
+
+        // every edit of the chain invalidates the cells before it
+        let length = 5_000;
+        for row in 1..length {
+            sheet
+                .set(&format!("B{}", row), &format!("=B{}", row + 1))
+                .unwrap();
+        }
+        let err = sheet.set(&format!("B{}", length), "=B1").unwrap_err();
+        assert!(matches!(err, SheetError::CircularReference(path) if path.len() == length + 1));
+    }
+
+    #[test]
+    fn test_operator_precedence() {
+        let mut sheet = Sheet::new();
+        for (formula, value) in [
+            ("=-2^2", -4.0),
+            ("=2^-1", 0.5),
+            ("=2^3^2", 512.0),
+            ("=-2*3+1", -5.0),
+            ("=8/2/2", 2.0),
+            ("=1-2-3", -4.0),
+            ("=2*(3+4)^2", 98.0),
+            ("=SUM(A2:A3, 1) - MAX(1, 2)", 2.0),
+        ] {
+            sheet.set("A1", formula).unwrap();
+            sheet.set("A2", "1").unwrap();
+            sheet.set("A3", "2").unwrap();
+            assert_eq!(sheet.value("A1"), Ok(value), "{}", formula);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Counters showing how much work caching of nodes saves
+
+use crate::trace::TraceEventKind;
+use std::cell::Cell;
+use std::ops::{Add, AddAssign};
+
+/// Cache counters of a node or a whole graph.
+///
+/// Inputs and constants keep no counters, as their values are given rather than cached.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Value was requested and taken from the cache
+    pub hits: u64,
+    /// Value was requested and had to be computed
+    pub misses: u64,
+    /// Cached value was discarded because some input changed
+    pub invalidations: u64,
+}
+
+impl CacheStats {
+    /// Share of requests served from the cache, 0 if there were none
+    pub fn hit_rate(&self) -> f64 {
+        let requests = self.hits + self.misses;
+        if requests == 0 {
+            0.0
+        } else {
+            self.hits as f64 / requests as f64
+        }
+    }
+
+    /// Counts event of a node
+    pub(crate) fn record(&mut self, kind: TraceEventKind) {
+        match kind {
+            TraceEventKind::CacheHit => self.hits += 1,
+            TraceEventKind::NodeComputed => self.misses += 1,
+            TraceEventKind::CacheInvalidated => self.invalidations += 1,
+            TraceEventKind::InputSet => {}
+        }
+    }
+}
+
+impl Add for CacheStats {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        CacheStats {
+            hits: self.hits + rhs.hits,
+            misses: self.misses + rhs.misses,
+            invalidations: self.invalidations + rhs.invalidations,
+        }
+    }
+}
+
+impl AddAssign for CacheStats {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+thread_local! {
+    static THREAD_STATS: Cell<CacheStats> = const { Cell::new(CacheStats { hits: 0, misses: 0, invalidations: 0 }) };
+}
+
+/// Totals of all nodes of [`comp_graph`](crate::comp_graph) and [`comp_graph2`](crate::comp_graph2)
+/// on the current thread, as their nodes don't know which graph they belong to
+pub fn thread_cache_stats() -> CacheStats {
+    THREAD_STATS.get()
+}
+
+/// Resets totals returned by [`thread_cache_stats`]
+pub fn reset_thread_cache_stats() {
+    THREAD_STATS.set(CacheStats::default());
+}
+
+/// Counts event in the counters of a node and in totals of the current thread
+pub(crate) fn record(node: &Cell<CacheStats>, kind: TraceEventKind) {
+    let mut stats = node.get();
+    stats.record(kind);
+    node.set(stats);
+    THREAD_STATS.with(|totals| {
+        let mut stats = totals.get();
+        stats.record(kind);
+        totals.set(stats);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_comp_graph() {
+        use crate::comp_graph::{builtins, InputNode};
+
+        let x1 = InputNode::new_input("x1");
+        let x2 = InputNode::new_input("x2");
+        x1.set(1.0f32);
+        x2.set(2.0f32);
+        let sum = builtins::add(x1.clone(), x2.clone());
+        let result = builtins::neg(sum.clone());
+        reset_thread_cache_stats();
+
+        result.compute();
+        result.compute();
+        x1.set(3.0);
+        result.compute();
+        assert_eq!(
+            sum.cache_stats(),
+            CacheStats {
+                hits: 0,
+                misses: 2,
+                invalidations: 1,
+            }
+        );
+        assert_eq!(result.cache_stats().hit_rate(), 1.0 / 3.0);
+        assert_eq!(
+            thread_cache_stats(),
+            CacheStats {
+                hits: 1,
+                misses: 4,
+                invalidations: 2,
+            }
+        );
+
+        result.reset_cache_stats();
+        assert_eq!(result.cache_stats(), CacheStats::default());
+        reset_thread_cache_stats();
+        assert_eq!(thread_cache_stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn test_comp_graph2() {
+        use crate::comp_graph2::{builtins, Compute, InputNode};
+
+        let x1 = InputNode::new_input("x1");
+        let x2 = InputNode::new_input("x2");
+        x1.set(1.0f32);
+        x2.set(2.0f32);
+        let sum = builtins::add(&x1, &x2);
+        let result = builtins::neg(&sum);
+        result.create_reverse_deps();
+        reset_thread_cache_stats();
+
+        result.compute();
+        result.compute();
+        x2.set(3.0);
+        result.compute();
+        assert_eq!(
+            sum.cache_stats(),
+            CacheStats {
+                hits: 0,
+                misses: 2,
+                invalidations: 1,
+            }
+        );
+        // inputs keep no counters
+        assert_eq!(x2.cache_stats(), CacheStats::default());
+        assert_eq!(
+            thread_cache_stats(),
+            CacheStats {
+                hits: 1,
+                misses: 4,
+                invalidations: 2,
+            }
+        );
+    }
+}
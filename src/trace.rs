@@ -0,0 +1,153 @@
+//! Structured events emitted by graphs to diagnose recomputation
+
+use std::cell::RefCell;
+
+/// What happened to a node
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TraceEventKind {
+    NodeComputed,
+    CacheHit,
+    CacheInvalidated,
+    InputSet,
+}
+
+/// Event emitted by a graph
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent<'a> {
+    pub kind: TraceEventKind,
+    /// Index of the node in [`CompGraph`](crate::comp_graph3::CompGraph)
+    /// or address of the node for other versions, as they have no ids
+    pub node: usize,
+    /// Name of the input or the operation of the node, if it is known
+    pub name: Option<&'a str>,
+    /// Label set with [`CompGraph::set_label`](crate::comp_graph3::CompGraph::set_label),
+    /// other versions have no labels
+    pub label: Option<&'a str>,
+}
+
+/// Callback receiving trace events
+pub type Tracer = Box<dyn FnMut(&TraceEvent)>;
+
+thread_local! {
+    static TRACER: RefCell<Option<Tracer>> = const { RefCell::new(None) };
+}
+
+/// Sets tracer for nodes of [`comp_graph`](crate::comp_graph) and [`comp_graph2`](crate::comp_graph2)
+/// on the current thread, returning the previous one.
+///
+/// Nodes of these versions don't know which graph they belong to, so unlike
+/// [`CompGraph::set_tracer`](crate::comp_graph3::CompGraph::set_tracer) tracer can't be set per graph.
+pub fn set_tracer(tracer: impl 'static + FnMut(&TraceEvent)) -> Option<Tracer> {
+    TRACER.with(|current| current.borrow_mut().replace(Box::new(tracer)))
+}
+
+/// Removes tracer of the current thread
+pub fn clear_tracer() -> Option<Tracer> {
+    TRACER.with(|current| current.borrow_mut().take())
+}
+
+/// Sends event to the tracer of the current thread if there is one.
+///
+/// Events caused by the tracer itself are dropped.
+pub(crate) fn emit(kind: TraceEventKind, node: usize, name: Option<&str>) {
+    TRACER.with(|current| {
+        if let Ok(mut current) = current.try_borrow_mut() {
+            if let Some(tracer) = &mut *current {
+                tracer(&TraceEvent {
+                    kind,
+                    node,
+                    name,
+                    label: None,
+                });
+            }
+        }
+    })
+}
+
+/// Address of `node` used to identify it in events
+pub(crate) fn address<T: ?Sized>(node: &T) -> usize {
+    (node as *const T).cast::<()>() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    type Recorded = Rc<RefCell<Vec<(TraceEventKind, usize, Option<String>)>>>;
+
+    fn record() -> Recorded {
+        let events = Rc::new(RefCell::new(vec![]));
+        let recorded = events.clone();
+        set_tracer(move |event| {
+            recorded
+                .borrow_mut()
+                .push((event.kind, event.node, event.name.map(str::to_owned)))
+        });
+        events
+    }
+
+    #[test]
+    fn test_comp_graph() {
+        use crate::comp_graph::{builtins, InputNode};
+        use TraceEventKind::*;
+
+        let x1 = InputNode::new_input("x1");
+        x1.set(1.0f32);
+        let result = builtins::neg(x1.clone());
+        let events = record();
+
+        result.compute();
+        result.compute();
+        x1.set(2.0);
+        clear_tracer();
+        result.compute();
+
+        let x1 = address(&*x1);
+        let result = address(&*result);
+        let name = |name: &str| Some(name.to_owned());
+        assert_eq!(
+            *events.borrow(),
+            [
+                (CacheHit, x1, name("x1")),
+                (NodeComputed, result, name("neg")),
+                (CacheHit, result, name("neg")),
+                (CacheInvalidated, x1, name("x1")),
+                (CacheInvalidated, result, name("neg")),
+                (InputSet, x1, name("x1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comp_graph2() {
+        use crate::comp_graph2::{builtins, Compute, InputNode};
+        use TraceEventKind::*;
+
+        let x1 = InputNode::new_input("x1");
+        x1.set(1.0f32);
+        let result = builtins::neg(&x1);
+        result.create_reverse_deps();
+        let events = record();
+
+        result.compute();
+        result.compute();
+        x1.set(2.0);
+        clear_tracer();
+
+        let x1 = address(&x1);
+        let result = address(&result);
+        let name = |name: &str| Some(name.to_owned());
+        assert_eq!(
+            *events.borrow(),
+            [
+                (CacheHit, x1, name("x1")),
+                (NodeComputed, result, name("neg")),
+                (CacheHit, result, name("neg")),
+                (CacheInvalidated, x1, None),
+                (CacheInvalidated, result, None),
+                (InputSet, x1, name("x1")),
+            ]
+        );
+    }
+}